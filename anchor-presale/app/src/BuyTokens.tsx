@@ -15,12 +15,18 @@ import React, { useState, useMemo, useCallback } from 'react';
 import { useConnection, useWallet } from '@solana/wallet-adapter-react';
 import { WalletMultiButton } from '@solana/wallet-adapter-react-ui';
 import { Program, AnchorProvider, web3, BN, utils } from '@coral-xyz/anchor';
-import { PublicKey, SystemProgram, Transaction } from '@solana/web3.js';
+import {
+  Ed25519Program,
+  PublicKey,
+  SystemProgram,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
+  Transaction,
+} from '@solana/web3.js';
 import { TOKEN_PROGRAM_ID } from '@solana/spl-token';
 
 // Import your IDL (generated from anchor build)
 import idl from './idl/anchor_presale.json';
-import type { SaleConfig, VoucherResponse, VoucherRequest } from './types';
+import type { Cluster, SaleConfig, VoucherResponse, VoucherRequest } from './types';
 
 // Program ID (update after deployment)
 const PROGRAM_ID = new PublicKey(idl.address);
@@ -28,6 +34,37 @@ const PROGRAM_ID = new PublicKey(idl.address);
 // Laravel backend URL
 const BACKEND_URL = process.env.REACT_APP_BACKEND_URL || 'http://localhost:8000';
 
+// Voucher message layout, must match VoucherData::message in the program
+const VOUCHER_DOMAIN = Buffer.from('anchor-presale:voucher');
+const VOUCHER_VERSION = 1;
+// Tag for each cluster, in the order of the program's Cluster enum
+const CLUSTER_TAGS: Record<string, number> = {
+  mainnetBeta: 0,
+  devnet: 1,
+  testnet: 2,
+  localnet: 3,
+};
+
+/**
+ * Rebuild the message the backend signed:
+ * domain + program id (32) + version (1) + cluster (1) + buyer (32) + sale (32)
+ * + max_allocation (8 LE) + nonce (8 LE) + expiry_ts (8 LE)
+ */
+const voucherMessage = (
+  voucher: { buyer: PublicKey; sale: PublicKey; maxAllocation: BN; nonce: BN; expiryTs: BN },
+  cluster: Cluster
+): Buffer =>
+  Buffer.concat([
+    VOUCHER_DOMAIN,
+    PROGRAM_ID.toBuffer(),
+    Buffer.from([VOUCHER_VERSION, CLUSTER_TAGS[Object.keys(cluster)[0]]]),
+    voucher.buyer.toBuffer(),
+    voucher.sale.toBuffer(),
+    voucher.maxAllocation.toArrayLike(Buffer, 'le', 8),
+    voucher.nonce.toArrayLike(Buffer, 'le', 8),
+    voucher.expiryTs.toArrayLike(Buffer, 'le', 8),
+  ]);
+
 export default function BuyTokens() {
   const { connection } = useConnection();
   const wallet = useWallet();
@@ -151,6 +188,35 @@ export default function BuyTokens() {
 
       console.log('Buyer Escrow PDA:', buyerEscrowPDA.toBase58());
 
+      const voucherData = {
+        buyer: new PublicKey(voucher.buyer),
+        sale: new PublicKey(voucher.sale),
+        maxAllocation: new BN(voucher.max_allocation),
+        nonce: new BN(voucher.nonce),
+        expiryTs: new BN(voucher.expiry_ts),
+      };
+
+      // Each voucher nonce can be redeemed once per buyer and sale
+      const [voucherNoncePDA] = await PublicKey.findProgramAddress(
+        [
+          Buffer.from('nonce'),
+          saleConfigPDA.toBuffer(),
+          wallet.publicKey.toBuffer(),
+          voucherData.nonce.toArrayLike(Buffer, 'le', 8),
+        ],
+        program.programId
+      );
+
+      // Sale vault holding the sale tokens, and the escrow for refundable payments
+      const [saleVaultPDA] = await PublicKey.findProgramAddress(
+        [Buffer.from('sale_vault'), saleConfigPDA.toBuffer()],
+        program.programId
+      );
+      const [paymentEscrowPDA] = await PublicKey.findProgramAddress(
+        [Buffer.from('payment_escrow'), saleConfigPDA.toBuffer()],
+        program.programId
+      );
+
       // Convert signature from base64 to bytes
       const signatureBytes = Buffer.from(voucherSig, 'base64');
       if (signatureBytes.length !== 64) {
@@ -173,28 +239,35 @@ export default function BuyTokens() {
         signatureLength: signatureBytes.length,
       });
 
+      // The program checks the signature through an Ed25519 instruction
+      // placed right before buy_with_voucher in the same transaction
+      const ed25519Ix = Ed25519Program.createInstructionWithPublicKey({
+        publicKey: signerPubkey.toBytes(),
+        message: voucherMessage(voucherData, saleConfigAccount.cluster),
+        signature: signatureBytes,
+      });
+
       // Call buy_with_voucher instruction
       // Parameters: allocation (u64), voucher (VoucherData), signature ([u8; 64])
       const tx = await program.methods
         .buyWithVoucher(
-          new BN(amount), // allocation: amount to purchase in lamports
-          {
-            buyer: new PublicKey(voucher.buyer),
-            sale: new PublicKey(voucher.sale),
-            maxAllocation: new BN(voucher.max_allocation),
-            nonce: new BN(voucher.nonce),
-            expiryTs: new BN(voucher.expiry_ts),
-          }, // voucher: VoucherData struct
+          new BN(amount), // allocation: amount to purchase in base units
+          voucherData, // voucher: VoucherData struct
           Array.from(signatureBytes) // signature: Ed25519 signature bytes
         )
         .accounts({
           saleConfig: saleConfigPDA,
           buyerEscrow: buyerEscrowPDA,
+          voucherNonce: voucherNoncePDA,
+          saleVault: saleVaultPDA,
           buyer: wallet.publicKey,
           treasury: saleConfigAccount.treasury,
+          paymentEscrow: paymentEscrowPDA,
           voucherSigner: signerPubkey,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
           systemProgram: SystemProgram.programId,
         })
+        .preInstructions([ed25519Ix])
         .rpc();
 
       console.log('Transaction signature:', tx);
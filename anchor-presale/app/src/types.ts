@@ -5,6 +5,15 @@
 import { PublicKey } from '@solana/web3.js';
 import { BN } from '@coral-xyz/anchor';
 
+/**
+ * Cluster a sale runs on, signed into its vouchers
+ */
+export type Cluster =
+  | { mainnetBeta: {} }
+  | { devnet: {} }
+  | { testnet: {} }
+  | { localnet: {} };
+
 /**
  * Sale configuration account structure
  */
@@ -12,6 +21,7 @@ export interface SaleConfig {
   owner: PublicKey;
   token_mint: PublicKey;
   treasury: PublicKey;
+  cluster: Cluster;
   price_lamports_per_token: BN;
   start_ts: BN;
   end_ts: BN;
@@ -1,6 +1,24 @@
 use anchor_lang::prelude::*;
-use anchor_lang::solana_program::{program::invoke, system_instruction};
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use anchor_lang::solana_program::{
+    ed25519_program,
+    hash::hashv,
+    program::{invoke, invoke_signed},
+    system_instruction,
+    sysvar::instructions::{
+        self as sysvar_instructions, load_current_index_checked, load_instruction_at_checked,
+    },
+};
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_spl::token_interface::{
+    self,
+    spl_token_2022::{
+        self,
+        extension::{
+            transfer_fee::TransferFeeConfig, BaseStateWithExtensions, StateWithExtensions,
+        },
+    },
+    Mint, TokenAccount, TokenInterface, TransferChecked,
+};
 
 declare_id!("7RMrnnQC1pckXgLWdqw6mqQT5QSmyUSKjcsHmTt5CTQV"); // TODO: Replace with your deployed program ID
 
@@ -12,35 +30,144 @@ pub mod anchor_presale {
     /// Only the sale owner can call this
     ///
     /// # Arguments
-    /// * `price_lamports_per_token` - Price in lamports per token (with decimals)
-    /// * `start_ts` - Unix timestamp when sale starts
-    /// * `end_ts` - Unix timestamp when sale ends
-    /// * `total_allocated` - Total tokens allocated for this sale
+    /// * `sale_id` - Identifier distinguishing this sale from the owner's other sales
+    /// * `params` - SaleParams struct containing price, timing, allocation and signer
     pub fn initialize_sale(
         ctx: Context<InitializeSale>,
-        price_lamports_per_token: u64,
-        start_ts: i64,
-        end_ts: i64,
-        total_allocated: u64,
+        sale_id: u64,
+        params: SaleParams,
     ) -> Result<()> {
+        let SaleParams {
+            price_lamports_per_token,
+            price_denominator,
+            start_ts,
+            end_ts,
+            total_allocated,
+            voucher_signer,
+            tge_ts,
+            max_per_wallet,
+            guardian,
+            soft_cap,
+            hard_cap_payment,
+            tier_mode,
+            price_tiers,
+            auction,
+            cluster,
+        } = params;
+
         require!(start_ts < end_ts, PresaleError::InvalidTimeRange);
         require!(total_allocated > 0, PresaleError::InvalidAllocation);
+        require!(max_per_wallet != Some(0), PresaleError::InvalidAllocation);
+        require!(soft_cap != Some(0), PresaleError::InvalidAllocation);
+        require!(price_denominator > 0, PresaleError::InvalidPrice);
+        validate_price_tiers(&price_tiers)?;
+        if let Some(auction) = &auction {
+            require!(
+                auction.start_price >= auction.floor_price && price_tiers.is_empty(),
+                PresaleError::InvalidAuction
+            );
+        }
+        let clearing = auction
+            .as_ref()
+            .is_some_and(|auction| auction.clearing_price);
+        if let Some(hard_cap) = hard_cap_payment {
+            require!(
+                hard_cap >= soft_cap.unwrap_or(1),
+                PresaleError::InvalidAllocation
+            );
+        }
+        // Refundable escrow only holds native SOL payments
+        require!(
+            (soft_cap.is_none() && !clearing) || ctx.accounts.payment_mint.is_none(),
+            PresaleError::InvalidPaymentMint
+        );
+
+        if soft_cap.is_some() || clearing {
+            // Make the payment escrow rent exempt so any payment can land in it
+            let rent_exempt = Rent::get()?.minimum_balance(0);
+            invoke(
+                &system_instruction::transfer(
+                    &ctx.accounts.owner.key(),
+                    &ctx.accounts.payment_escrow.key(),
+                    rent_exempt,
+                ),
+                &[
+                    ctx.accounts.owner.to_account_info(),
+                    ctx.accounts.payment_escrow.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+            )?;
+        }
 
         let sale_config = &mut ctx.accounts.sale_config;
         sale_config.owner = ctx.accounts.owner.key();
+        sale_config.creator = ctx.accounts.owner.key();
+        sale_config.pending_owner = None;
+        sale_config.sale_id = sale_id;
         sale_config.token_mint = ctx.accounts.token_mint.key();
         sale_config.treasury = ctx.accounts.treasury.key();
+        sale_config.payment_mint = ctx.accounts.payment_mint.as_ref().map(|mint| mint.key());
+        sale_config.voucher_signer = voucher_signer;
+        sale_config.merkle_root = None;
+        sale_config.cluster = cluster;
         sale_config.price_lamports_per_token = price_lamports_per_token;
+        sale_config.price_denominator = price_denominator;
+        sale_config.token_decimals = ctx.accounts.token_mint.decimals;
         sale_config.start_ts = start_ts;
         sale_config.end_ts = end_ts;
         sale_config.total_allocated = total_allocated;
+        sale_config.max_per_wallet = max_per_wallet;
         sale_config.sold = 0;
+        sale_config.claimed = 0;
+        sale_config.tge_ts = tge_ts;
+        sale_config.guardian = guardian;
+        sale_config.paused = false;
+        sale_config.claims_paused = false;
+        sale_config.soft_cap = soft_cap;
+        sale_config.raised = 0;
+        sale_config.hard_cap_payment = hard_cap_payment;
+        sale_config.tier_mode = tier_mode;
+        sale_config.price_tiers = price_tiers;
+        sale_config.auction = auction;
+        sale_config.last_price = 0;
+        sale_config.completed = false;
+        sale_config.finalized = false;
+        sale_config.refunding = false;
         sale_config.bump = ctx.bumps.sale_config;
+        sale_config.vault_bump = ctx.bumps.sale_vault;
+        sale_config.payment_escrow_bump = ctx.bumps.payment_escrow;
+
+        emit!(SaleInitialized {
+            sale: sale_config.key(),
+            owner: sale_config.owner,
+            sale_id,
+            token_mint: sale_config.token_mint,
+            treasury: sale_config.treasury,
+            payment_mint: sale_config.payment_mint,
+            voucher_signer,
+            cluster,
+            price_lamports_per_token,
+            price_denominator,
+            start_ts,
+            end_ts,
+            total_allocated,
+            token_decimals: sale_config.token_decimals,
+            tge_ts,
+            max_per_wallet,
+            guardian,
+            soft_cap,
+            hard_cap_payment,
+            tier_mode,
+            price_tiers: sale_config.price_tiers.clone(),
+            auction,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
 
         msg!(
-            "Sale initialized: {} tokens at {} lamports per token",
+            "Sale initialized: {} tokens at {}/{} lamports per token",
             total_allocated,
-            price_lamports_per_token
+            price_lamports_per_token,
+            price_denominator
         );
 
         Ok(())
@@ -49,6 +176,9 @@ pub mod anchor_presale {
     /// Buy tokens using a voucher signed by the backend voucher signer
     /// Verifies the ed25519 signature and transfers SOL to treasury
     ///
+    /// The transaction must carry an Ed25519Program instruction immediately
+    /// before this one, signing the voucher message with the voucher signer key.
+    ///
     /// # Arguments
     /// * `allocation` - Amount of tokens to purchase
     /// * `voucher` - VoucherData struct containing buyer, sale, max_allocation, nonce, expiry
@@ -63,107 +193,90 @@ pub mod anchor_presale {
         let clock = Clock::get()?;
         let current_ts = clock.unix_timestamp;
 
-        // Validate sale timing
-        require!(
-            current_ts >= sale_config.start_ts,
-            PresaleError::SaleNotStarted
-        );
-        require!(current_ts <= sale_config.end_ts, PresaleError::SaleEnded);
-
-        // Validate voucher
         require!(
-            voucher.buyer == ctx.accounts.buyer.key(),
-            PresaleError::InvalidVoucher
+            sale_config.payment_mint.is_none(),
+            PresaleError::InvalidPaymentMint
         );
-        require!(
-            voucher.sale == sale_config.key(),
-            PresaleError::InvalidVoucher
-        );
-        require!(
-            voucher.expiry_ts >= current_ts,
-            PresaleError::VoucherExpired
-        );
-        require!(
-            allocation <= voucher.max_allocation,
-            PresaleError::ExceedsAllocation
-        );
-        require!(allocation > 0, PresaleError::InvalidAllocation);
 
-        // Validate supply
-        let new_sold = sale_config
-            .sold
-            .checked_add(allocation)
-            .ok_or(PresaleError::Overflow)?;
-        require!(
-            new_sold <= sale_config.total_allocated,
-            PresaleError::InsufficientSupply
-        );
+        // Replay protection first, so a redeemed voucher always fails with
+        // VoucherAlreadyUsed rather than whichever check it trips next
+        record_nonce(
+            &mut ctx.accounts.voucher_nonce,
+            &voucher,
+            ctx.bumps.voucher_nonce,
+        )?;
 
-        // Verify voucher signature using ed25519 instruction
-        // The voucher message is: buyer (32) + sale (32) + max_allocation (8) + nonce (8) + expiry_ts (8)
-        let mut message = Vec::new();
-        message.extend_from_slice(&voucher.buyer.to_bytes());
-        message.extend_from_slice(&voucher.sale.to_bytes());
-        message.extend_from_slice(&voucher.max_allocation.to_le_bytes());
-        message.extend_from_slice(&voucher.nonce.to_le_bytes());
-        message.extend_from_slice(&voucher.expiry_ts.to_le_bytes());
+        validate_purchase(
+            sale_config,
+            &ctx.accounts.sale_vault,
+            allocation,
+            current_ts,
+        )?;
+        validate_voucher(
+            &voucher,
+            sale_config,
+            ctx.accounts.buyer.key(),
+            ctx.accounts.buyer_escrow.allocation,
+            allocation,
+            current_ts,
+        )?;
 
         // Verify signature using ed25519 program
         verify_ed25519_signature(
-            &message,
+            &ctx.accounts.instructions,
+            &voucher.message(sale_config.cluster),
             &signature,
             &ctx.accounts.voucher_signer.key().to_bytes(),
         )?;
 
-        // Check for replay attack - ensure this nonce hasn't been used for this buyer+sale
-        let buyer_escrow = &mut ctx.accounts.buyer_escrow;
-        require!(
-            buyer_escrow.allocation == 0,
-            PresaleError::VoucherAlreadyUsed
-        );
-
         // Calculate payment amount in lamports
-        let payment_lamports = allocation
-            .checked_mul(sale_config.price_lamports_per_token)
-            .ok_or(PresaleError::Overflow)?;
-
-        // Transfer SOL from buyer to treasury
-        // Option 1: Using system_instruction::transfer (for SOL payments)
+        let payment_lamports = calculate_payment(sale_config, allocation, current_ts)?;
+        record_auction_price(sale_config, current_ts)?;
+        validate_payment(sale_config, payment_lamports)?;
+
+        // Transfer SOL from buyer to treasury, or to the payment escrow
+        // until finalize_sale when the sale has a soft cap or clearing price
+        let destination = if escrows_payments(sale_config) {
+            ctx.accounts.payment_escrow.to_account_info()
+        } else {
+            ctx.accounts.treasury.to_account_info()
+        };
         invoke(
             &system_instruction::transfer(
                 &ctx.accounts.buyer.key(),
-                &ctx.accounts.treasury.key(),
+                destination.key,
                 payment_lamports,
             ),
             &[
                 ctx.accounts.buyer.to_account_info(),
-                ctx.accounts.treasury.to_account_info(),
+                destination,
                 ctx.accounts.system_program.to_account_info(),
             ],
         )?;
 
-        // Option 2: For USDC/SPL token payments (uncomment if using SPL tokens)
-        // token::transfer(
-        //     CpiContext::new(
-        //         ctx.accounts.token_program.to_account_info(),
-        //         Transfer {
-        //             from: ctx.accounts.buyer_payment_token_account.to_account_info(),
-        //             to: ctx.accounts.treasury_token_account.to_account_info(),
-        //             authority: ctx.accounts.buyer.to_account_info(),
-        //         },
-        //     ),
-        //     payment_lamports, // or payment_tokens
-        // )?;
-
-        // Update buyer escrow
-        buyer_escrow.sale = sale_config.key();
-        buyer_escrow.buyer = ctx.accounts.buyer.key();
-        buyer_escrow.allocation = allocation;
-        buyer_escrow.claimed = 0;
-        buyer_escrow.bump = ctx.bumps.buyer_escrow;
+        record_purchase(
+            sale_config,
+            &mut ctx.accounts.buyer_escrow,
+            ctx.accounts.buyer.key(),
+            allocation,
+            payment_lamports,
+            ctx.bumps.buyer_escrow,
+        )?;
 
-        // Update sale sold amount
-        sale_config.sold = new_sold;
+        emit!(TokensPurchased {
+            sale: sale_config.key(),
+            buyer: ctx.accounts.buyer.key(),
+            payment_mint: None,
+            allocation,
+            payment: payment_lamports,
+            nonce: Some(voucher.nonce),
+            tier: None,
+            buyer_allocation: ctx.accounts.buyer_escrow.allocation,
+            buyer_paid: ctx.accounts.buyer_escrow.paid,
+            sold: sale_config.sold,
+            raised: sale_config.raised,
+            timestamp: current_ts,
+        });
 
         msg!(
             "Buyer {} purchased {} tokens for {} lamports",
@@ -175,237 +288,1662 @@ pub mod anchor_presale {
         Ok(())
     }
 
-    /// Create a vesting schedule for a beneficiary
-    /// Only the sale owner can call this
+    /// Buy tokens with an SPL payment token (USDC, USDT, ...) using a voucher
+    /// Same voucher rules as `buy_with_voucher`, but the sale must have been
+    /// initialized with a payment mint and the price is in its base units
     ///
     /// # Arguments
-    /// * `beneficiary` - Wallet that will receive vested tokens
-    /// * `total_amount` - Total tokens to vest
-    /// * `start_ts` - Vesting start timestamp
-    /// * `cliff_seconds` - Cliff period in seconds (no tokens before this)
-    /// * `duration_seconds` - Total vesting duration in seconds
-    /// * `revocable` - Whether owner can revoke this vesting
-    pub fn create_vesting(
-        ctx: Context<CreateVesting>,
-        beneficiary: Pubkey,
-        total_amount: u64,
-        start_ts: i64,
-        cliff_seconds: u64,
-        duration_seconds: u64,
-        revocable: bool,
+    /// * `allocation` - Amount of tokens to purchase
+    /// * `voucher` - VoucherData struct containing buyer, sale, max_allocation, nonce, expiry
+    /// * `signature` - Ed25519 signature from backend voucher signer (64 bytes)
+    pub fn buy_with_voucher_spl(
+        ctx: Context<BuyWithVoucherSpl>,
+        allocation: u64,
+        voucher: VoucherData,
+        signature: [u8; 64],
     ) -> Result<()> {
-        require!(total_amount > 0, PresaleError::InvalidAllocation);
-        require!(duration_seconds > 0, PresaleError::InvalidDuration);
+        let sale_config = &mut ctx.accounts.sale_config;
+        let clock = Clock::get()?;
+        let current_ts = clock.unix_timestamp;
+
+        record_nonce(
+            &mut ctx.accounts.voucher_nonce,
+            &voucher,
+            ctx.bumps.voucher_nonce,
+        )?;
+
+        validate_purchase(
+            sale_config,
+            &ctx.accounts.sale_vault,
+            allocation,
+            current_ts,
+        )?;
+        validate_voucher(
+            &voucher,
+            sale_config,
+            ctx.accounts.buyer.key(),
+            ctx.accounts.buyer_escrow.allocation,
+            allocation,
+            current_ts,
+        )?;
+
+        verify_ed25519_signature(
+            &ctx.accounts.instructions,
+            &voucher.message(sale_config.cluster),
+            &signature,
+            &ctx.accounts.voucher_signer.key().to_bytes(),
+        )?;
+
+        // Payment amount in payment mint base units; the buyer covers any
+        // Token-2022 transfer fee so the treasury receives the full price
+        let payment_amount = calculate_payment(sale_config, allocation, current_ts)?;
+        record_auction_price(sale_config, current_ts)?;
+        validate_payment(sale_config, payment_amount)?;
+        let payment_with_fee = add_transfer_fee(&ctx.accounts.payment_mint, payment_amount)?;
+
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.buyer_payment_token_account.to_account_info(),
+                    mint: ctx.accounts.payment_mint.to_account_info(),
+                    to: ctx.accounts.treasury_token_account.to_account_info(),
+                    authority: ctx.accounts.buyer.to_account_info(),
+                },
+            ),
+            payment_with_fee,
+            ctx.accounts.payment_mint.decimals,
+        )?;
+
+        record_purchase(
+            sale_config,
+            &mut ctx.accounts.buyer_escrow,
+            ctx.accounts.buyer.key(),
+            allocation,
+            payment_amount,
+            ctx.bumps.buyer_escrow,
+        )?;
+
+        emit!(TokensPurchased {
+            sale: sale_config.key(),
+            buyer: ctx.accounts.buyer.key(),
+            payment_mint: Some(ctx.accounts.payment_mint.key()),
+            allocation,
+            payment: payment_amount,
+            nonce: Some(voucher.nonce),
+            tier: None,
+            buyer_allocation: ctx.accounts.buyer_escrow.allocation,
+            buyer_paid: ctx.accounts.buyer_escrow.paid,
+            sold: sale_config.sold,
+            raised: sale_config.raised,
+            timestamp: current_ts,
+        });
+
+        msg!(
+            "Buyer {} purchased {} tokens for {} of {}",
+            ctx.accounts.buyer.key(),
+            allocation,
+            payment_amount,
+            ctx.accounts.payment_mint.key()
+        );
+
+        Ok(())
+    }
+
+    /// Buy tokens as an allowlisted wallet, proven against the sale's merkle root
+    /// Same supply, timing and escrow rules as `buy_with_voucher`, without a
+    /// backend signer; the leaf's max_allocation caps the buyer's lifetime total
+    ///
+    /// # Arguments
+    /// * `allocation` - Amount of tokens to purchase
+    /// * `max_allocation` - Max tokens the buyer can purchase, as committed in the leaf
+    /// * `tier` - Allowlist tier assigned to the buyer, as committed in the leaf
+    /// * `proof` - Sibling hashes from the buyer's leaf up to the root
+    pub fn buy_with_proof(
+        ctx: Context<BuyWithProof>,
+        allocation: u64,
+        max_allocation: u64,
+        tier: u8,
+        proof: Vec<[u8; 32]>,
+    ) -> Result<()> {
+        let sale_config = &mut ctx.accounts.sale_config;
+        let clock = Clock::get()?;
+        let current_ts = clock.unix_timestamp;
+
         require!(
-            cliff_seconds <= duration_seconds,
-            PresaleError::InvalidCliff
+            sale_config.payment_mint.is_none(),
+            PresaleError::InvalidPaymentMint
         );
+        let merkle_root = sale_config
+            .merkle_root
+            .ok_or(PresaleError::MerkleRootNotSet)?;
 
-        let vesting = &mut ctx.accounts.vesting;
-        vesting.beneficiary = beneficiary;
-        vesting.total_amount = total_amount;
-        vesting.released = 0;
-        vesting.start_ts = start_ts;
-        vesting.cliff_seconds = cliff_seconds;
-        vesting.duration_seconds = duration_seconds;
-        vesting.revocable = revocable;
-        vesting.revoked = false;
-        vesting.bump = ctx.bumps.vesting;
+        validate_purchase(
+            sale_config,
+            &ctx.accounts.sale_vault,
+            allocation,
+            current_ts,
+        )?;
+        validate_allocation_cap(
+            sale_config,
+            max_allocation,
+            ctx.accounts.buyer_escrow.allocation,
+            allocation,
+        )?;
+
+        let leaf = allowlist_leaf(&ctx.accounts.buyer.key(), max_allocation, tier);
+        require!(
+            verify_merkle_proof(&proof, &merkle_root, leaf),
+            PresaleError::InvalidMerkleProof
+        );
+
+        let payment_lamports = calculate_payment(sale_config, allocation, current_ts)?;
+        record_auction_price(sale_config, current_ts)?;
+        validate_payment(sale_config, payment_lamports)?;
+
+        let destination = if escrows_payments(sale_config) {
+            ctx.accounts.payment_escrow.to_account_info()
+        } else {
+            ctx.accounts.treasury.to_account_info()
+        };
+        invoke(
+            &system_instruction::transfer(
+                &ctx.accounts.buyer.key(),
+                destination.key,
+                payment_lamports,
+            ),
+            &[
+                ctx.accounts.buyer.to_account_info(),
+                destination,
+                ctx.accounts.system_program.to_account_info(),
+            ],
+        )?;
+
+        record_purchase(
+            sale_config,
+            &mut ctx.accounts.buyer_escrow,
+            ctx.accounts.buyer.key(),
+            allocation,
+            payment_lamports,
+            ctx.bumps.buyer_escrow,
+        )?;
+
+        emit!(TokensPurchased {
+            sale: sale_config.key(),
+            buyer: ctx.accounts.buyer.key(),
+            payment_mint: None,
+            allocation,
+            payment: payment_lamports,
+            nonce: None,
+            tier: Some(tier),
+            buyer_allocation: ctx.accounts.buyer_escrow.allocation,
+            buyer_paid: ctx.accounts.buyer_escrow.paid,
+            sold: sale_config.sold,
+            raised: sale_config.raised,
+            timestamp: current_ts,
+        });
 
         msg!(
-            "Vesting created for {} with {} tokens over {} seconds",
-            beneficiary,
-            total_amount,
-            duration_seconds
+            "Allowlisted buyer {} (tier {}) purchased {} tokens for {} lamports",
+            ctx.accounts.buyer.key(),
+            tier,
+            allocation,
+            payment_lamports
         );
 
         Ok(())
     }
 
-    /// Claim vested tokens based on the vesting schedule
-    /// Beneficiary can call this to claim their vested tokens
-    pub fn claim_vested(ctx: Context<ClaimVested>) -> Result<()> {
-        let vesting = &mut ctx.accounts.vesting;
+    /// Deposit sale tokens into the sale vault
+    /// Only the sale owner can call this
+    /// With a Token-2022 transfer fee the owner pays the fee on top, so the
+    /// vault receives the full `amount`
+    ///
+    /// # Arguments
+    /// * `amount` - Tokens to move from the owner's token account into the vault
+    pub fn fund_sale(ctx: Context<FundSale>, amount: u64) -> Result<()> {
+        require!(amount > 0, PresaleError::InvalidAllocation);
+
+        // Owner covers any Token-2022 transfer fee so the vault holds the full amount
+        let deposit = add_transfer_fee(&ctx.accounts.token_mint, amount)?;
+
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.owner_token_account.to_account_info(),
+                    mint: ctx.accounts.token_mint.to_account_info(),
+                    to: ctx.accounts.sale_vault.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            deposit,
+            ctx.accounts.token_mint.decimals,
+        )?;
+
+        ctx.accounts.sale_vault.reload()?;
+        emit!(SaleFunded {
+            sale: ctx.accounts.sale_config.key(),
+            owner: ctx.accounts.owner.key(),
+            amount,
+            vault_balance: ctx.accounts.sale_vault.amount,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!("Sale funded with {} tokens", amount);
+
+        Ok(())
+    }
+
+    /// Withdraw tokens the sale no longer owes anyone after it has ended
+    /// Only the sale owner can call this
+    /// Sold but unclaimed tokens stay in the vault for their buyers
+    pub fn withdraw_unsold(ctx: Context<WithdrawUnsold>) -> Result<()> {
+        let sale_config = &ctx.accounts.sale_config;
         let clock = Clock::get()?;
-        let current_ts = clock.unix_timestamp;
 
-        require!(!vesting.revoked, PresaleError::VestingRevoked);
         require!(
-            vesting.beneficiary == ctx.accounts.beneficiary.key(),
-            PresaleError::Unauthorized
+            clock.unix_timestamp > sale_config.end_ts,
+            PresaleError::SaleNotEnded
         );
 
-        // Calculate vested amount
-        let vested_amount = calculate_vested_amount(vesting, current_ts)?;
-        let claimable = vested_amount
-            .checked_sub(vesting.released)
+        let outstanding = sale_config
+            .sold
+            .checked_sub(sale_config.claimed)
             .ok_or(PresaleError::Underflow)?;
+        let unsold = ctx
+            .accounts
+            .sale_vault
+            .amount
+            .checked_sub(outstanding)
+            .ok_or(PresaleError::Underflow)?;
+        require!(unsold > 0, PresaleError::NothingToClaim);
 
-        require!(claimable > 0, PresaleError::NothingToClaim);
-
-        // Transfer tokens from vesting vault to beneficiary
-        let vesting_account_info = vesting.to_account_info();
         let seeds = &[
-            b"vesting".as_ref(),
-            vesting.beneficiary.as_ref(),
-            &[vesting.bump],
+            b"sale_config".as_ref(),
+            sale_config.creator.as_ref(),
+            &sale_config.sale_id.to_le_bytes(),
+            &[sale_config.bump],
         ];
         let signer = &[&seeds[..]];
 
-        token::transfer(
+        token_interface::transfer_checked(
             CpiContext::new_with_signer(
                 ctx.accounts.token_program.to_account_info(),
-                Transfer {
-                    from: ctx.accounts.vesting_vault.to_account_info(),
-                    to: ctx.accounts.beneficiary_token_account.to_account_info(),
-                    authority: vesting_account_info,
+                TransferChecked {
+                    from: ctx.accounts.sale_vault.to_account_info(),
+                    mint: ctx.accounts.token_mint.to_account_info(),
+                    to: ctx.accounts.owner_token_account.to_account_info(),
+                    authority: sale_config.to_account_info(),
                 },
                 signer,
             ),
-            claimable,
+            unsold,
+            ctx.accounts.token_mint.decimals,
         )?;
 
-        // Update released amount
-        vesting.released = vesting
-            .released
-            .checked_add(claimable)
-            .ok_or(PresaleError::Overflow)?;
+        emit!(UnsoldWithdrawn {
+            sale: sale_config.key(),
+            owner: ctx.accounts.owner.key(),
+            destination: ctx.accounts.owner_token_account.key(),
+            amount: unsold,
+            outstanding,
+            timestamp: clock.unix_timestamp,
+        });
 
-        msg!(
-            "Claimed {} vested tokens for {}",
-            claimable,
-            vesting.beneficiary
-        );
+        msg!("Withdrew {} unsold tokens", unsold);
 
         Ok(())
     }
 
-    /// Revoke a vesting schedule (if revocable)
-    /// Only the sale owner can call this
-    /// Returns unvested tokens to treasury
-    pub fn revoke_vesting(ctx: Context<RevokeVesting>) -> Result<()> {
-        let vesting = &mut ctx.accounts.vesting;
+    /// Claim purchased tokens from the sale vault after TGE
+    /// Buyer receives everything allocated to them that has not been claimed yet,
+    /// less any Token-2022 transfer fee charged by the mint
+    pub fn claim_purchased(ctx: Context<ClaimPurchased>) -> Result<()> {
+        let sale_config = &mut ctx.accounts.sale_config;
+        let buyer_escrow = &mut ctx.accounts.buyer_escrow;
         let clock = Clock::get()?;
-        let current_ts = clock.unix_timestamp;
 
-        require!(vesting.revocable, PresaleError::NotRevocable);
-        require!(!vesting.revoked, PresaleError::AlreadyRevoked);
+        require!(!sale_config.claims_paused, PresaleError::ClaimsPaused);
+        if sale_config.soft_cap.is_some() {
+            require!(sale_config.finalized, PresaleError::SaleNotFinalized);
+            require!(!sale_config.refunding, PresaleError::SoftCapNotMet);
+        }
+        require!(
+            clock.unix_timestamp >= sale_config.tge_ts,
+            PresaleError::ClaimNotStarted
+        );
 
-        // Calculate vested amount at current time
-        let vested_amount = calculate_vested_amount(vesting, current_ts)?;
-        let unvested = vesting
-            .total_amount
-            .checked_sub(vested_amount)
+        let claimable = buyer_escrow
+            .allocation
+            .checked_sub(buyer_escrow.claimed)
             .ok_or(PresaleError::Underflow)?;
+        require!(claimable > 0, PresaleError::NothingToClaim);
 
-        // Transfer unvested tokens back to treasury/escrow
-        let vesting_account_info = vesting.to_account_info();
+        // Transfer tokens from sale vault to buyer, signed by the sale PDA
         let seeds = &[
-            b"vesting".as_ref(),
-            vesting.beneficiary.as_ref(),
-            &[vesting.bump],
+            b"sale_config".as_ref(),
+            sale_config.creator.as_ref(),
+            &sale_config.sale_id.to_le_bytes(),
+            &[sale_config.bump],
         ];
         let signer = &[&seeds[..]];
 
-        if unvested > 0 {
-            token::transfer(
-                CpiContext::new_with_signer(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.vesting_vault.to_account_info(),
-                        to: ctx.accounts.treasury_token_account.to_account_info(),
-                        authority: vesting_account_info,
-                    },
-                    signer,
-                ),
-                unvested,
-            )?;
-        }
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.sale_vault.to_account_info(),
+                    mint: ctx.accounts.token_mint.to_account_info(),
+                    to: ctx.accounts.buyer_token_account.to_account_info(),
+                    authority: sale_config.to_account_info(),
+                },
+                signer,
+            ),
+            claimable,
+            ctx.accounts.token_mint.decimals,
+        )?;
 
-        // Mark as revoked
-        vesting.revoked = true;
+        buyer_escrow.claimed = buyer_escrow
+            .claimed
+            .checked_add(claimable)
+            .ok_or(PresaleError::Overflow)?;
+        sale_config.claimed = sale_config
+            .claimed
+            .checked_add(claimable)
+            .ok_or(PresaleError::Overflow)?;
+
+        emit!(PurchaseClaimed {
+            sale: sale_config.key(),
+            buyer: buyer_escrow.buyer,
+            amount: claimable,
+            buyer_allocation: buyer_escrow.allocation,
+            buyer_claimed: buyer_escrow.claimed,
+            claimed: sale_config.claimed,
+            timestamp: clock.unix_timestamp,
+        });
 
         msg!(
-            "Vesting revoked for {}, returned {} unvested tokens",
-            vesting.beneficiary,
-            unvested
+            "Buyer {} claimed {} purchased tokens",
+            buyer_escrow.buyer,
+            claimable
         );
 
         Ok(())
     }
+
+    /// Settle a sale after it has ended
+    /// Anyone can call this, but only for sales that escrow payments; other
+    /// sales pay the treasury at purchase time and have nothing to settle
+    ///
+    /// With a soft cap, escrowed payments go to the treasury if the cap was
+    /// met; otherwise refunds are enabled and the escrow stays with buyers.
+    /// With a clearing-price auction, only `sold * last_price` goes to the
+    /// treasury and the rest stays in escrow for claim_rebate.
+    pub fn finalize_sale(ctx: Context<FinalizeSale>) -> Result<()> {
+        let sale_config = &mut ctx.accounts.sale_config;
+        let clock = Clock::get()?;
+
+        // A sold-out sale can settle without waiting for end_ts
+        require!(
+            clock.unix_timestamp > sale_config.end_ts || sale_config.completed,
+            PresaleError::SaleNotEnded
+        );
+        require!(!sale_config.finalized, PresaleError::SaleAlreadyFinalized);
+        require!(
+            escrows_payments(sale_config),
+            PresaleError::PaymentsNotEscrowed
+        );
+
+        sale_config.finalized = true;
+
+        let soft_cap_missed = sale_config
+            .soft_cap
+            .is_some_and(|soft_cap| sale_config.raised < soft_cap);
+        // Lamports released from the payment escrow to the treasury
+        let mut proceeds = 0;
+        if soft_cap_missed {
+            sale_config.refunding = true;
+        } else {
+            proceeds = if clearing_enabled(sale_config) {
+                clearing_cost(sale_config, sale_config.sold)?
+            } else {
+                sale_config.raised
+            };
+
+            let sale_key = sale_config.key();
+            let seeds = &[
+                b"payment_escrow".as_ref(),
+                sale_key.as_ref(),
+                &[sale_config.payment_escrow_bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            invoke_signed(
+                &system_instruction::transfer(
+                    &ctx.accounts.payment_escrow.key(),
+                    &ctx.accounts.treasury.key(),
+                    proceeds,
+                ),
+                &[
+                    ctx.accounts.payment_escrow.to_account_info(),
+                    ctx.accounts.treasury.to_account_info(),
+                    ctx.accounts.system_program.to_account_info(),
+                ],
+                signer,
+            )?;
+        }
+
+        emit!(SaleFinalized {
+            sale: sale_config.key(),
+            treasury: sale_config.treasury,
+            proceeds,
+            sold: sale_config.sold,
+            raised: sale_config.raised,
+            clearing_price: clearing_enabled(sale_config).then_some(sale_config.last_price),
+            refunding: sale_config.refunding,
+            timestamp: clock.unix_timestamp,
+        });
+
+        msg!(
+            "Sale {} finalized: raised {}, refunding {}",
+            sale_config.key(),
+            sale_config.raised,
+            sale_config.refunding
+        );
+
+        Ok(())
+    }
+
+    /// Refund a buyer's payment when the sale missed its soft cap
+    /// Returns the escrowed lamports and closes the buyer escrow
+    pub fn refund(ctx: Context<Refund>) -> Result<()> {
+        let sale_config = &mut ctx.accounts.sale_config;
+        let buyer_escrow = &ctx.accounts.buyer_escrow;
+
+        require!(sale_config.refunding, PresaleError::RefundsNotEnabled);
+
+        let sale_key = sale_config.key();
+        let seeds = &[
+            b"payment_escrow".as_ref(),
+            sale_key.as_ref(),
+            &[sale_config.payment_escrow_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        invoke_signed(
+            &system_instruction::transfer(
+                &ctx.accounts.payment_escrow.key(),
+                &ctx.accounts.buyer.key(),
+                buyer_escrow.paid,
+            ),
+            &[
+                ctx.accounts.payment_escrow.to_account_info(),
+                ctx.accounts.buyer.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+            signer,
+        )?;
+
+        // Refunded tokens are no longer owed, so the owner can withdraw them
+        sale_config.sold = sale_config
+            .sold
+            .checked_sub(buyer_escrow.allocation)
+            .ok_or(PresaleError::Underflow)?;
+        sale_config.raised = sale_config
+            .raised
+            .checked_sub(buyer_escrow.paid)
+            .ok_or(PresaleError::Underflow)?;
+
+        emit!(Refunded {
+            sale: sale_key,
+            buyer: buyer_escrow.buyer,
+            amount: buyer_escrow.paid,
+            allocation: buyer_escrow.allocation,
+            sold: sale_config.sold,
+            raised: sale_config.raised,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Refunded {} lamports to {}",
+            buyer_escrow.paid,
+            buyer_escrow.buyer
+        );
+
+        Ok(())
+    }
+
+    /// Return what a buyer paid above the auction's clearing price
+    /// Only after a clearing-price auction has been finalized
+    pub fn claim_rebate(ctx: Context<ClaimRebate>) -> Result<()> {
+        let sale_config = &ctx.accounts.sale_config;
+        let buyer_escrow = &mut ctx.accounts.buyer_escrow;
+
+        require!(
+            clearing_enabled(sale_config),
+            PresaleError::ClearingPriceNotEnabled
+        );
+        require!(sale_config.finalized, PresaleError::SaleNotFinalized);
+        require!(!sale_config.refunding, PresaleError::SoftCapNotMet);
+
+        let owed = clearing_cost(sale_config, buyer_escrow.allocation)?;
+        let rebate = buyer_escrow
+            .paid
+            .checked_sub(owed)
+            .ok_or(PresaleError::Underflow)?;
+        require!(rebate > 0, PresaleError::NothingToClaim);
+
+        let sale_key = sale_config.key();
+        let seeds = &[
+            b"payment_escrow".as_ref(),
+            sale_key.as_ref(),
+            &[sale_config.payment_escrow_bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        invoke_signed(
+            &system_instruction::transfer(
+                &ctx.accounts.payment_escrow.key(),
+                &ctx.accounts.buyer.key(),
+                rebate,
+            ),
+            &[
+                ctx.accounts.payment_escrow.to_account_info(),
+                ctx.accounts.buyer.to_account_info(),
+                ctx.accounts.system_program.to_account_info(),
+            ],
+            signer,
+        )?;
+
+        buyer_escrow.paid = owed;
+
+        emit!(RebateClaimed {
+            sale: sale_key,
+            buyer: buyer_escrow.buyer,
+            amount: rebate,
+            clearing_price: sale_config.last_price,
+            buyer_allocation: buyer_escrow.allocation,
+            buyer_paid: buyer_escrow.paid,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Rebated {} lamports to {} at clearing price {}",
+            rebate,
+            buyer_escrow.buyer,
+            sale_config.last_price
+        );
+
+        Ok(())
+    }
+
+    /// Replace the voucher signer key (e.g. after a backend key leak)
+    /// Only the sale owner can call this
+    ///
+    /// # Arguments
+    /// * `new_voucher_signer` - New backend key authorized to sign vouchers
+    pub fn rotate_voucher_signer(
+        ctx: Context<RotateVoucherSigner>,
+        new_voucher_signer: Pubkey,
+    ) -> Result<()> {
+        let sale_config = &mut ctx.accounts.sale_config;
+        let old_voucher_signer = sale_config.voucher_signer;
+        sale_config.voucher_signer = new_voucher_signer;
+
+        emit!(VoucherSignerRotated {
+            sale: sale_config.key(),
+            old_voucher_signer,
+            new_voucher_signer,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Voucher signer rotated from {} to {}",
+            old_voucher_signer,
+            new_voucher_signer
+        );
+
+        Ok(())
+    }
+
+    /// Set or clear the allowlist merkle root used by `buy_with_proof`
+    /// Only the sale owner can call this
+    ///
+    /// # Arguments
+    /// * `merkle_root` - Root over (buyer, max_allocation, tier) leaves, None to disable
+    pub fn set_merkle_root(
+        ctx: Context<SetMerkleRoot>,
+        merkle_root: Option<[u8; 32]>,
+    ) -> Result<()> {
+        let sale_config = &mut ctx.accounts.sale_config;
+        sale_config.merkle_root = merkle_root;
+
+        emit!(MerkleRootSet {
+            sale: sale_config.key(),
+            merkle_root,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Sale {} allowlist {}",
+            sale_config.key(),
+            if merkle_root.is_some() {
+                "set"
+            } else {
+                "cleared"
+            }
+        );
+
+        Ok(())
+    }
+
+    /// Update sale parameters after initialization
+    /// Only the sale owner can call this
+    ///
+    /// Price and start time are fixed once the sale has started, the end time
+    /// can only be extended and the allocation cannot drop below tokens sold.
+    /// An auction's end time is fixed too, since it sets the price decay.
+    /// Once the sale has ended, its end time and allocation are fixed, and an
+    /// escrowed sale cannot be extended past MAX_ESCROW_SALE_DURATION.
+    ///
+    /// # Arguments
+    /// * `params` - UpdateSaleParams struct; `None` fields are left unchanged
+    pub fn update_sale(ctx: Context<UpdateSale>, params: UpdateSaleParams) -> Result<()> {
+        let sale_config = &mut ctx.accounts.sale_config;
+        let current_ts = Clock::get()?.unix_timestamp;
+        let started = current_ts >= sale_config.start_ts;
+        let ended = current_ts > sale_config.end_ts;
+
+        require!(!sale_config.finalized, PresaleError::SaleAlreadyFinalized);
+
+        let new_price = params
+            .price_lamports_per_token
+            .unwrap_or(sale_config.price_lamports_per_token);
+        let new_start_ts = params.start_ts.unwrap_or(sale_config.start_ts);
+        let new_end_ts = params.end_ts.unwrap_or(sale_config.end_ts);
+        let new_total_allocated = params
+            .total_allocated
+            .unwrap_or(sale_config.total_allocated);
+        let new_treasury = params.treasury.unwrap_or(sale_config.treasury);
+
+        if started {
+            require!(
+                new_price == sale_config.price_lamports_per_token,
+                PresaleError::SaleAlreadyStarted
+            );
+            require!(
+                new_start_ts == sale_config.start_ts,
+                PresaleError::SaleAlreadyStarted
+            );
+            // Moving the end would raise the auction price mid-sale
+            require!(
+                sale_config.auction.is_none() || new_end_ts == sale_config.end_ts,
+                PresaleError::SaleAlreadyStarted
+            );
+        }
+        // Reopening an ended sale would let it sell again before settling
+        if ended {
+            require!(new_end_ts == sale_config.end_ts, PresaleError::SaleEnded);
+            require!(
+                new_total_allocated == sale_config.total_allocated,
+                PresaleError::SaleEnded
+            );
+        }
+        require!(
+            new_end_ts >= sale_config.end_ts,
+            PresaleError::InvalidTimeRange
+        );
+        if new_end_ts != sale_config.end_ts && escrows_payments(sale_config) {
+            require!(
+                new_end_ts - new_start_ts <= MAX_ESCROW_SALE_DURATION,
+                PresaleError::InvalidTimeRange
+            );
+        }
+        require!(new_start_ts < new_end_ts, PresaleError::InvalidTimeRange);
+        require!(
+            new_total_allocated > 0 && new_total_allocated >= sale_config.sold,
+            PresaleError::InvalidAllocation
+        );
+
+        emit!(SaleUpdated {
+            sale: sale_config.key(),
+            old_price_lamports_per_token: sale_config.price_lamports_per_token,
+            new_price_lamports_per_token: new_price,
+            old_start_ts: sale_config.start_ts,
+            new_start_ts,
+            old_end_ts: sale_config.end_ts,
+            new_end_ts,
+            old_total_allocated: sale_config.total_allocated,
+            new_total_allocated,
+            old_treasury: sale_config.treasury,
+            new_treasury,
+            timestamp: current_ts,
+        });
+
+        sale_config.price_lamports_per_token = new_price;
+        sale_config.start_ts = new_start_ts;
+        sale_config.end_ts = new_end_ts;
+        sale_config.total_allocated = new_total_allocated;
+        sale_config.treasury = new_treasury;
+        sale_config.completed = sale_completed(sale_config);
+
+        msg!("Sale {} updated", sale_config.key());
+
+        Ok(())
+    }
+
+    /// Propose a new sale owner (e.g. a multisig)
+    /// Only the sale owner can call this; the new owner must call accept_owner
+    ///
+    /// # Arguments
+    /// * `new_owner` - Key that will become the owner once it accepts
+    pub fn propose_owner(ctx: Context<ProposeOwner>, new_owner: Pubkey) -> Result<()> {
+        let sale_config = &mut ctx.accounts.sale_config;
+        sale_config.pending_owner = Some(new_owner);
+
+        emit!(OwnershipProposed {
+            sale: sale_config.key(),
+            owner: sale_config.owner,
+            pending_owner: new_owner,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Ownership of sale {} proposed to {}",
+            sale_config.key(),
+            new_owner
+        );
+
+        Ok(())
+    }
+
+    /// Accept a pending ownership transfer
+    /// Only the proposed owner can call this
+    pub fn accept_owner(ctx: Context<AcceptOwner>) -> Result<()> {
+        let sale_config = &mut ctx.accounts.sale_config;
+        let old_owner = sale_config.owner;
+        sale_config.owner = ctx.accounts.new_owner.key();
+        sale_config.pending_owner = None;
+
+        emit!(OwnershipTransferred {
+            sale: sale_config.key(),
+            old_owner,
+            new_owner: sale_config.owner,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Sale owner changed from {} to {}",
+            old_owner,
+            sale_config.owner
+        );
+
+        Ok(())
+    }
+
+    /// Pause purchases (and optionally purchase claims) in an emergency,
+    /// e.g. when the voucher backend is compromised
+    /// Callable by the sale owner or the guardian
+    ///
+    /// Pausing again never reopens claims; only resume_sale does
+    ///
+    /// # Arguments
+    /// * `pause_claims` - Also block claim_purchased until resumed
+    pub fn pause_sale(ctx: Context<PauseSale>, pause_claims: bool) -> Result<()> {
+        let sale_config = &mut ctx.accounts.sale_config;
+        sale_config.paused = true;
+        sale_config.claims_paused |= pause_claims;
+
+        emit!(SalePaused {
+            sale: sale_config.key(),
+            authority: ctx.accounts.authority.key(),
+            claims_paused: sale_config.claims_paused,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Sale {} paused by {} (claims paused: {})",
+            sale_config.key(),
+            ctx.accounts.authority.key(),
+            sale_config.claims_paused
+        );
+
+        Ok(())
+    }
+
+    /// Resume purchases and claims after a pause
+    /// Callable by the sale owner or the guardian
+    pub fn resume_sale(ctx: Context<PauseSale>) -> Result<()> {
+        let sale_config = &mut ctx.accounts.sale_config;
+        sale_config.paused = false;
+        sale_config.claims_paused = false;
+
+        emit!(SaleResumed {
+            sale: sale_config.key(),
+            authority: ctx.accounts.authority.key(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Sale {} resumed by {}",
+            sale_config.key(),
+            ctx.accounts.authority.key()
+        );
+
+        Ok(())
+    }
+
+    /// Create a vesting schedule for a beneficiary
+    /// Only the sale owner can call this
+    /// Funds the schedule's vault with `total_amount` from the owner's token account
+    ///
+    /// # Arguments
+    /// * `beneficiary` - Wallet that will receive vested tokens
+    /// * `schedule_index` - Distinguishes this schedule from the beneficiary's other
+    ///   schedules in the same sale
+    /// * `total_amount` - Total tokens to vest
+    /// * `start_ts` - Vesting start timestamp
+    /// * `cliff_seconds` - Cliff period in seconds (no tokens before this)
+    /// * `duration_seconds` - Total vesting duration in seconds
+    /// * `revocable` - Whether owner can revoke this vesting
+    #[allow(clippy::too_many_arguments)]
+    pub fn create_vesting(
+        ctx: Context<CreateVesting>,
+        beneficiary: Pubkey,
+        schedule_index: u64,
+        total_amount: u64,
+        start_ts: i64,
+        cliff_seconds: u64,
+        duration_seconds: u64,
+        revocable: bool,
+    ) -> Result<()> {
+        require!(total_amount > 0, PresaleError::InvalidAllocation);
+        require!(duration_seconds > 0, PresaleError::InvalidDuration);
+        require!(
+            cliff_seconds <= duration_seconds,
+            PresaleError::InvalidCliff
+        );
+
+        // Owner covers any Token-2022 transfer fee so the vault holds the full amount
+        let deposit = add_transfer_fee(&ctx.accounts.token_mint, total_amount)?;
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.owner_token_account.to_account_info(),
+                    mint: ctx.accounts.token_mint.to_account_info(),
+                    to: ctx.accounts.vesting_vault.to_account_info(),
+                    authority: ctx.accounts.owner.to_account_info(),
+                },
+            ),
+            deposit,
+            ctx.accounts.token_mint.decimals,
+        )?;
+
+        let vesting = &mut ctx.accounts.vesting;
+        vesting.sale = ctx.accounts.sale_config.key();
+        vesting.beneficiary = beneficiary;
+        vesting.schedule_index = schedule_index;
+        vesting.token_mint = ctx.accounts.token_mint.key();
+        vesting.total_amount = total_amount;
+        vesting.released = 0;
+        vesting.start_ts = start_ts;
+        vesting.cliff_seconds = cliff_seconds;
+        vesting.duration_seconds = duration_seconds;
+        vesting.revocable = revocable;
+        vesting.revoked = false;
+        vesting.revoked_ts = 0;
+        vesting.bump = ctx.bumps.vesting;
+        vesting.vault_bump = ctx.bumps.vesting_vault;
+
+        emit!(VestingCreated {
+            sale: vesting.sale,
+            vesting: vesting.key(),
+            beneficiary,
+            token_mint: vesting.token_mint,
+            schedule_index,
+            total_amount,
+            start_ts,
+            cliff_seconds,
+            duration_seconds,
+            revocable,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        msg!(
+            "Vesting created for {} with {} tokens over {} seconds",
+            beneficiary,
+            total_amount,
+            duration_seconds
+        );
+
+        Ok(())
+    }
+
+    /// Claim vested tokens based on the vesting schedule
+    /// Beneficiary can call this to claim their vested tokens, including
+    /// tokens that had vested before the schedule was revoked
+    pub fn claim_vested(ctx: Context<ClaimVested>) -> Result<()> {
+        let vesting = &mut ctx.accounts.vesting;
+        let clock = Clock::get()?;
+        let current_ts = clock.unix_timestamp;
+
+        require!(
+            vesting.beneficiary == ctx.accounts.beneficiary.key(),
+            PresaleError::Unauthorized
+        );
+
+        // Calculate vested amount
+        let vested_amount = calculate_vested_amount(vesting, current_ts)?;
+        let claimable = vested_amount
+            .checked_sub(vesting.released)
+            .ok_or(PresaleError::Underflow)?;
+
+        // Nothing vests after revocation, so an empty claim there is final
+        if vesting.revoked {
+            require!(claimable > 0, PresaleError::VestingRevoked);
+        }
+        require!(claimable > 0, PresaleError::NothingToClaim);
+
+        // Transfer tokens from vesting vault to beneficiary
+        let vesting_account_info = vesting.to_account_info();
+        let seeds = &[
+            b"vesting".as_ref(),
+            vesting.sale.as_ref(),
+            vesting.beneficiary.as_ref(),
+            &vesting.schedule_index.to_le_bytes(),
+            &[vesting.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                TransferChecked {
+                    from: ctx.accounts.vesting_vault.to_account_info(),
+                    mint: ctx.accounts.token_mint.to_account_info(),
+                    to: ctx.accounts.beneficiary_token_account.to_account_info(),
+                    authority: vesting_account_info,
+                },
+                signer,
+            ),
+            claimable,
+            ctx.accounts.token_mint.decimals,
+        )?;
+
+        // Update released amount
+        vesting.released = vesting
+            .released
+            .checked_add(claimable)
+            .ok_or(PresaleError::Overflow)?;
+
+        emit!(VestingClaimed {
+            sale: vesting.sale,
+            vesting: vesting.key(),
+            beneficiary: vesting.beneficiary,
+            amount: claimable,
+            released: vesting.released,
+            total_amount: vesting.total_amount,
+            timestamp: current_ts,
+        });
+
+        msg!(
+            "Claimed {} vested tokens for {}",
+            claimable,
+            vesting.beneficiary
+        );
+
+        Ok(())
+    }
+
+    /// Revoke a vesting schedule (if revocable)
+    /// Only the sale owner can call this
+    /// Returns unvested tokens to treasury; tokens vested so far stay in the
+    /// vault for the beneficiary to claim
+    pub fn revoke_vesting(ctx: Context<RevokeVesting>) -> Result<()> {
+        let vesting = &mut ctx.accounts.vesting;
+        let clock = Clock::get()?;
+        let current_ts = clock.unix_timestamp;
+
+        require!(vesting.revocable, PresaleError::NotRevocable);
+        require!(!vesting.revoked, PresaleError::AlreadyRevoked);
+
+        // Calculate vested amount at current time
+        let vested_amount = calculate_vested_amount(vesting, current_ts)?;
+        let unvested = vesting
+            .total_amount
+            .checked_sub(vested_amount)
+            .ok_or(PresaleError::Underflow)?;
+
+        // Transfer unvested tokens back to the sale treasury
+        let vesting_account_info = vesting.to_account_info();
+        let seeds = &[
+            b"vesting".as_ref(),
+            vesting.sale.as_ref(),
+            vesting.beneficiary.as_ref(),
+            &vesting.schedule_index.to_le_bytes(),
+            &[vesting.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        if unvested > 0 {
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    TransferChecked {
+                        from: ctx.accounts.vesting_vault.to_account_info(),
+                        mint: ctx.accounts.token_mint.to_account_info(),
+                        to: ctx.accounts.treasury_token_account.to_account_info(),
+                        authority: vesting_account_info,
+                    },
+                    signer,
+                ),
+                unvested,
+                ctx.accounts.token_mint.decimals,
+            )?;
+        }
+
+        // Mark as revoked, freezing the vested amount at this time
+        vesting.revoked = true;
+        vesting.revoked_ts = current_ts;
+
+        emit!(VestingRevoked {
+            sale: vesting.sale,
+            vesting: vesting.key(),
+            beneficiary: vesting.beneficiary,
+            destination: ctx.accounts.treasury_token_account.key(),
+            unvested,
+            vested: vested_amount,
+            released: vesting.released,
+            timestamp: current_ts,
+        });
+
+        msg!(
+            "Vesting revoked for {}, returned {} unvested tokens",
+            vesting.beneficiary,
+            unvested
+        );
+
+        Ok(())
+    }
+}
+
+// ============================================================================
+// Account Structs
+// ============================================================================
+
+#[derive(Accounts)]
+#[instruction(sale_id: u64)]
+pub struct InitializeSale<'info> {
+    /// Seeded by the initializer, which stays fixed if ownership is transferred
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + SaleConfig::INIT_SPACE,
+        seeds = [b"sale_config", owner.key().as_ref(), &sale_id.to_le_bytes()],
+        bump
+    )]
+    pub sale_config: Account<'info, SaleConfig>,
+
+    /// Token mint for the sale
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    /// Vault holding the tokens being sold, owned by the sale PDA
+    #[account(
+        init,
+        payer = owner,
+        seeds = [b"sale_vault", sale_config.key().as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = sale_config,
+        token::token_program = token_program
+    )]
+    pub sale_vault: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: Treasury account to receive payments
+    pub treasury: AccountInfo<'info>,
+
+    /// SPL payment mint (USDC, USDT, ...); omit to take payment in native SOL
+    pub payment_mint: Option<InterfaceAccount<'info, Mint>>,
+
+    /// Holds SOL payments until finalize_sale when the sale has a soft cap
+    #[account(
+        mut,
+        seeds = [b"payment_escrow", sale_config.key().as_ref()],
+        bump
+    )]
+    pub payment_escrow: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(allocation: u64, voucher: VoucherData)]
+pub struct BuyWithVoucher<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"sale_config",
+            sale_config.creator.as_ref(),
+            &sale_config.sale_id.to_le_bytes()
+        ],
+        bump = sale_config.bump,
+        has_one = voucher_signer @ PresaleError::InvalidVoucherSigner
+    )]
+    pub sale_config: Account<'info, SaleConfig>,
+
+    /// Accumulates every voucher redeemed by this buyer in this sale
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + BuyerEscrow::INIT_SPACE,
+        seeds = [b"buyer_escrow", sale_config.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub buyer_escrow: Account<'info, BuyerEscrow>,
+
+    /// Marks the voucher nonce as redeemed; record_nonce rejects a replay
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + VoucherNonce::INIT_SPACE,
+        seeds = [
+            b"nonce",
+            sale_config.key().as_ref(),
+            buyer.key().as_ref(),
+            &voucher.nonce.to_le_bytes()
+        ],
+        bump
+    )]
+    pub voucher_nonce: Account<'info, VoucherNonce>,
+
+    #[account(
+        seeds = [b"sale_vault", sale_config.key().as_ref()],
+        bump = sale_config.vault_bump
+    )]
+    pub sale_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    /// CHECK: Treasury receives SOL payment, must match sale_config.treasury
+    #[account(mut, address = sale_config.treasury @ PresaleError::InvalidTreasury)]
+    pub treasury: AccountInfo<'info>,
+
+    /// Receives the payment instead of the treasury when the sale has a soft cap
+    #[account(
+        mut,
+        seeds = [b"payment_escrow", sale_config.key().as_ref()],
+        bump = sale_config.payment_escrow_bump
+    )]
+    pub payment_escrow: SystemAccount<'info>,
+
+    /// CHECK: Voucher signer public key, must match sale_config.voucher_signer
+    pub voucher_signer: AccountInfo<'info>,
+
+    /// CHECK: Instructions sysvar, used to introspect the Ed25519 instruction
+    #[account(address = sysvar_instructions::ID)]
+    pub instructions: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
 }
 
-// ============================================================================
-// Account Structs
-// ============================================================================
+#[derive(Accounts)]
+#[instruction(allocation: u64, voucher: VoucherData)]
+pub struct BuyWithVoucherSpl<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"sale_config",
+            sale_config.creator.as_ref(),
+            &sale_config.sale_id.to_le_bytes()
+        ],
+        bump = sale_config.bump,
+        has_one = voucher_signer @ PresaleError::InvalidVoucherSigner,
+        constraint = sale_config.payment_mint == Some(payment_mint.key()) @ PresaleError::InvalidPaymentMint
+    )]
+    pub sale_config: Account<'info, SaleConfig>,
+
+    /// Accumulates every voucher redeemed by this buyer in this sale
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + BuyerEscrow::INIT_SPACE,
+        seeds = [b"buyer_escrow", sale_config.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub buyer_escrow: Account<'info, BuyerEscrow>,
+
+    /// Marks the voucher nonce as redeemed; record_nonce rejects a replay
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + VoucherNonce::INIT_SPACE,
+        seeds = [
+            b"nonce",
+            sale_config.key().as_ref(),
+            buyer.key().as_ref(),
+            &voucher.nonce.to_le_bytes()
+        ],
+        bump
+    )]
+    pub voucher_nonce: Account<'info, VoucherNonce>,
+
+    #[account(
+        seeds = [b"sale_vault", sale_config.key().as_ref()],
+        bump = sale_config.vault_bump
+    )]
+    pub sale_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    pub payment_mint: InterfaceAccount<'info, Mint>,
+
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        token::authority = buyer
+    )]
+    pub buyer_payment_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Treasury's token account for the payment mint
+    #[account(
+        mut,
+        token::mint = payment_mint,
+        constraint = treasury_token_account.owner == sale_config.treasury @ PresaleError::InvalidTreasury
+    )]
+    pub treasury_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: Voucher signer public key, must match sale_config.voucher_signer
+    pub voucher_signer: AccountInfo<'info>,
+
+    /// CHECK: Instructions sysvar, used to introspect the Ed25519 instruction
+    #[account(address = sysvar_instructions::ID)]
+    pub instructions: AccountInfo<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BuyWithProof<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"sale_config",
+            sale_config.creator.as_ref(),
+            &sale_config.sale_id.to_le_bytes()
+        ],
+        bump = sale_config.bump
+    )]
+    pub sale_config: Account<'info, SaleConfig>,
+
+    /// Shared with voucher purchases, so the leaf cap covers both
+    #[account(
+        init_if_needed,
+        payer = buyer,
+        space = 8 + BuyerEscrow::INIT_SPACE,
+        seeds = [b"buyer_escrow", sale_config.key().as_ref(), buyer.key().as_ref()],
+        bump
+    )]
+    pub buyer_escrow: Account<'info, BuyerEscrow>,
+
+    #[account(
+        seeds = [b"sale_vault", sale_config.key().as_ref()],
+        bump = sale_config.vault_bump
+    )]
+    pub sale_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    /// CHECK: Treasury receives SOL payment, must match sale_config.treasury
+    #[account(mut, address = sale_config.treasury @ PresaleError::InvalidTreasury)]
+    pub treasury: AccountInfo<'info>,
+
+    /// Receives the payment instead of the treasury when the sale has a soft cap
+    #[account(
+        mut,
+        seeds = [b"payment_escrow", sale_config.key().as_ref()],
+        bump = sale_config.payment_escrow_bump
+    )]
+    pub payment_escrow: SystemAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FundSale<'info> {
+    #[account(
+        seeds = [
+            b"sale_config",
+            sale_config.creator.as_ref(),
+            &sale_config.sale_id.to_le_bytes()
+        ],
+        bump = sale_config.bump,
+        has_one = owner,
+        has_one = token_mint
+    )]
+    pub sale_config: Account<'info, SaleConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"sale_vault", sale_config.key().as_ref()],
+        bump = sale_config.vault_bump
+    )]
+    pub sale_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = token_mint,
+        token::authority = owner
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    pub owner: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawUnsold<'info> {
+    #[account(
+        seeds = [
+            b"sale_config",
+            sale_config.creator.as_ref(),
+            &sale_config.sale_id.to_le_bytes()
+        ],
+        bump = sale_config.bump,
+        has_one = owner,
+        has_one = token_mint
+    )]
+    pub sale_config: Account<'info, SaleConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"sale_vault", sale_config.key().as_ref()],
+        bump = sale_config.vault_bump
+    )]
+    pub sale_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = token_mint,
+        token::authority = owner
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    pub owner: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimPurchased<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"sale_config",
+            sale_config.creator.as_ref(),
+            &sale_config.sale_id.to_le_bytes()
+        ],
+        bump = sale_config.bump,
+        has_one = token_mint
+    )]
+    pub sale_config: Account<'info, SaleConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"buyer_escrow", sale_config.key().as_ref(), buyer.key().as_ref()],
+        bump = buyer_escrow.bump,
+        has_one = buyer
+    )]
+    pub buyer_escrow: Account<'info, BuyerEscrow>,
+
+    #[account(
+        mut,
+        seeds = [b"sale_vault", sale_config.key().as_ref()],
+        bump = sale_config.vault_bump
+    )]
+    pub sale_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        associated_token::mint = token_mint,
+        associated_token::authority = buyer,
+        associated_token::token_program = token_program
+    )]
+    pub buyer_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
+    pub buyer: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeSale<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"sale_config",
+            sale_config.creator.as_ref(),
+            &sale_config.sale_id.to_le_bytes()
+        ],
+        bump = sale_config.bump
+    )]
+    pub sale_config: Account<'info, SaleConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"payment_escrow", sale_config.key().as_ref()],
+        bump = sale_config.payment_escrow_bump
+    )]
+    pub payment_escrow: SystemAccount<'info>,
+
+    /// CHECK: Treasury receives escrowed SOL, must match sale_config.treasury
+    #[account(mut, address = sale_config.treasury @ PresaleError::InvalidTreasury)]
+    pub treasury: AccountInfo<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Refund<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"sale_config",
+            sale_config.creator.as_ref(),
+            &sale_config.sale_id.to_le_bytes()
+        ],
+        bump = sale_config.bump
+    )]
+    pub sale_config: Account<'info, SaleConfig>,
+
+    #[account(
+        mut,
+        close = buyer,
+        seeds = [b"buyer_escrow", sale_config.key().as_ref(), buyer.key().as_ref()],
+        bump = buyer_escrow.bump,
+        has_one = buyer
+    )]
+    pub buyer_escrow: Account<'info, BuyerEscrow>,
+
+    #[account(
+        mut,
+        seeds = [b"payment_escrow", sale_config.key().as_ref()],
+        bump = sale_config.payment_escrow_bump
+    )]
+    pub payment_escrow: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimRebate<'info> {
+    #[account(
+        seeds = [
+            b"sale_config",
+            sale_config.creator.as_ref(),
+            &sale_config.sale_id.to_le_bytes()
+        ],
+        bump = sale_config.bump
+    )]
+    pub sale_config: Account<'info, SaleConfig>,
+
+    #[account(
+        mut,
+        seeds = [b"buyer_escrow", sale_config.key().as_ref(), buyer.key().as_ref()],
+        bump = buyer_escrow.bump,
+        has_one = buyer
+    )]
+    pub buyer_escrow: Account<'info, BuyerEscrow>,
+
+    #[account(
+        mut,
+        seeds = [b"payment_escrow", sale_config.key().as_ref()],
+        bump = sale_config.payment_escrow_bump
+    )]
+    pub payment_escrow: SystemAccount<'info>,
+
+    #[account(mut)]
+    pub buyer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RotateVoucherSigner<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"sale_config",
+            sale_config.creator.as_ref(),
+            &sale_config.sale_id.to_le_bytes()
+        ],
+        bump = sale_config.bump,
+        has_one = owner
+    )]
+    pub sale_config: Account<'info, SaleConfig>,
+
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SetMerkleRoot<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"sale_config",
+            sale_config.creator.as_ref(),
+            &sale_config.sale_id.to_le_bytes()
+        ],
+        bump = sale_config.bump,
+        has_one = owner
+    )]
+    pub sale_config: Account<'info, SaleConfig>,
+
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateSale<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"sale_config",
+            sale_config.creator.as_ref(),
+            &sale_config.sale_id.to_le_bytes()
+        ],
+        bump = sale_config.bump,
+        has_one = owner
+    )]
+    pub sale_config: Account<'info, SaleConfig>,
+
+    pub owner: Signer<'info>,
+}
 
 #[derive(Accounts)]
-pub struct InitializeSale<'info> {
+pub struct ProposeOwner<'info> {
     #[account(
-        init,
-        payer = owner,
-        space = 8 + SaleConfig::INIT_SPACE,
-        seeds = [b"sale_config", owner.key().as_ref()],
-        bump
+        mut,
+        seeds = [
+            b"sale_config",
+            sale_config.creator.as_ref(),
+            &sale_config.sale_id.to_le_bytes()
+        ],
+        bump = sale_config.bump,
+        has_one = owner
     )]
     pub sale_config: Account<'info, SaleConfig>,
 
-    /// CHECK: Token mint for the sale
-    pub token_mint: AccountInfo<'info>,
-
-    /// CHECK: Treasury account to receive payments
-    pub treasury: AccountInfo<'info>,
-
-    #[account(mut)]
     pub owner: Signer<'info>,
-
-    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct BuyWithVoucher<'info> {
+pub struct AcceptOwner<'info> {
     #[account(
         mut,
-        seeds = [b"sale_config", sale_config.owner.as_ref()],
-        bump = sale_config.bump
+        seeds = [
+            b"sale_config",
+            sale_config.creator.as_ref(),
+            &sale_config.sale_id.to_le_bytes()
+        ],
+        bump = sale_config.bump,
+        constraint = sale_config.pending_owner == Some(new_owner.key()) @ PresaleError::Unauthorized
     )]
     pub sale_config: Account<'info, SaleConfig>,
 
+    pub new_owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct PauseSale<'info> {
     #[account(
-        init,
-        payer = buyer,
-        space = 8 + BuyerEscrow::INIT_SPACE,
-        seeds = [b"buyer_escrow", sale_config.key().as_ref(), buyer.key().as_ref()],
-        bump
+        mut,
+        seeds = [
+            b"sale_config",
+            sale_config.creator.as_ref(),
+            &sale_config.sale_id.to_le_bytes()
+        ],
+        bump = sale_config.bump,
+        constraint = authority.key() == sale_config.owner
+            || authority.key() == sale_config.guardian @ PresaleError::Unauthorized
     )]
-    pub buyer_escrow: Account<'info, BuyerEscrow>,
-
-    #[account(mut)]
-    pub buyer: Signer<'info>,
-
-    /// CHECK: Treasury receives SOL payment
-    #[account(mut)]
-    pub treasury: AccountInfo<'info>,
-
-    /// CHECK: Voucher signer public key (backend server key)
-    pub voucher_signer: AccountInfo<'info>,
+    pub sale_config: Account<'info, SaleConfig>,
 
-    // Uncomment for SPL token payments (USDC, etc.)
-    // #[account(mut)]
-    // pub buyer_payment_token_account: Account<'info, TokenAccount>,
-    // #[account(mut)]
-    // pub treasury_token_account: Account<'info, TokenAccount>,
-    // pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
+    /// Sale owner or guardian
+    pub authority: Signer<'info>,
 }
 
 #[derive(Accounts)]
-#[instruction(beneficiary: Pubkey)]
+#[instruction(beneficiary: Pubkey, schedule_index: u64)]
 pub struct CreateVesting<'info> {
     #[account(
-        seeds = [b"sale_config", sale_config.owner.as_ref()],
+        seeds = [
+            b"sale_config",
+            sale_config.creator.as_ref(),
+            &sale_config.sale_id.to_le_bytes()
+        ],
         bump = sale_config.bump,
-        has_one = owner
+        has_one = owner,
+        has_one = token_mint
     )]
     pub sale_config: Account<'info, SaleConfig>,
 
@@ -413,14 +1951,41 @@ pub struct CreateVesting<'info> {
         init,
         payer = owner,
         space = 8 + Vesting::INIT_SPACE,
-        seeds = [b"vesting", beneficiary.as_ref()],
+        seeds = [
+            b"vesting",
+            sale_config.key().as_ref(),
+            beneficiary.as_ref(),
+            &schedule_index.to_le_bytes()
+        ],
         bump
     )]
     pub vesting: Account<'info, Vesting>,
 
+    /// Vault holding the vesting tokens, owned by the vesting PDA
+    #[account(
+        init,
+        payer = owner,
+        seeds = [b"vesting_vault", vesting.key().as_ref()],
+        bump,
+        token::mint = token_mint,
+        token::authority = vesting,
+        token::token_program = token_program
+    )]
+    pub vesting_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        token::mint = token_mint,
+        token::authority = owner
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
+
     #[account(mut)]
     pub owner: Signer<'info>,
 
+    pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
 }
 
@@ -428,48 +1993,87 @@ pub struct CreateVesting<'info> {
 pub struct ClaimVested<'info> {
     #[account(
         mut,
-        seeds = [b"vesting", beneficiary.key().as_ref()],
+        seeds = [
+            b"vesting",
+            vesting.sale.as_ref(),
+            beneficiary.key().as_ref(),
+            &vesting.schedule_index.to_le_bytes()
+        ],
         bump = vesting.bump,
-        has_one = beneficiary
+        has_one = beneficiary,
+        has_one = token_mint
     )]
     pub vesting: Account<'info, Vesting>,
 
-    #[account(mut)]
-    pub vesting_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"vesting_vault", vesting.key().as_ref()],
+        bump = vesting.vault_bump,
+        token::mint = token_mint,
+        token::authority = vesting
+    )]
+    pub vesting_vault: InterfaceAccount<'info, TokenAccount>,
 
-    #[account(mut)]
-    pub beneficiary_token_account: Account<'info, TokenAccount>,
+    #[account(mut, token::mint = token_mint)]
+    pub beneficiary_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
 
     pub beneficiary: Signer<'info>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
 pub struct RevokeVesting<'info> {
     #[account(
-        seeds = [b"sale_config", sale_config.owner.as_ref()],
+        seeds = [
+            b"sale_config",
+            sale_config.creator.as_ref(),
+            &sale_config.sale_id.to_le_bytes()
+        ],
         bump = sale_config.bump,
-        has_one = owner
+        has_one = owner,
+        has_one = token_mint
     )]
     pub sale_config: Account<'info, SaleConfig>,
 
+    /// Seeds tie the vesting to the owner's sale
     #[account(
         mut,
-        seeds = [b"vesting", vesting.beneficiary.as_ref()],
-        bump = vesting.bump
+        seeds = [
+            b"vesting",
+            sale_config.key().as_ref(),
+            vesting.beneficiary.as_ref(),
+            &vesting.schedule_index.to_le_bytes()
+        ],
+        bump = vesting.bump,
+        has_one = token_mint
     )]
     pub vesting: Account<'info, Vesting>,
 
-    #[account(mut)]
-    pub vesting_vault: Account<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [b"vesting_vault", vesting.key().as_ref()],
+        bump = vesting.vault_bump,
+        token::mint = token_mint,
+        token::authority = vesting
+    )]
+    pub vesting_vault: InterfaceAccount<'info, TokenAccount>,
 
-    #[account(mut)]
-    pub treasury_token_account: Account<'info, TokenAccount>,
+    /// Sale treasury's token account receiving the unvested tokens
+    #[account(
+        mut,
+        token::mint = token_mint,
+        constraint = treasury_token_account.owner == sale_config.treasury @ PresaleError::InvalidTreasury
+    )]
+    pub treasury_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_mint: InterfaceAccount<'info, Mint>,
 
     pub owner: Signer<'info>,
 
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 // ============================================================================
@@ -481,21 +2085,52 @@ pub struct RevokeVesting<'info> {
 #[derive(InitSpace)]
 pub struct SaleConfig {
     pub owner: Pubkey,                 // Sale owner/admin
+    pub creator: Pubkey,               // Initializer, fixed PDA seed (owner can change)
+    pub pending_owner: Option<Pubkey>, // Proposed owner awaiting accept_owner
+    pub sale_id: u64,                  // Distinguishes the creator's sales
     pub token_mint: Pubkey,            // Token being sold
     pub treasury: Pubkey,              // Treasury receiving payments
-    pub price_lamports_per_token: u64, // Price per token in lamports
+    pub payment_mint: Option<Pubkey>,  // SPL payment mint, None for native SOL
+    pub voucher_signer: Pubkey,        // Backend key that signs vouchers
+    pub merkle_root: Option<[u8; 32]>, // Allowlist root for buy_with_proof, None to disable
+    pub cluster: Cluster,              // Cluster vouchers must be signed for
+    pub price_lamports_per_token: u64, // Price numerator per whole token, lamports or payment mint units
+    pub price_denominator: u64,        // Denominator shared by every price in the sale
+    pub token_decimals: u8,            // Decimals of token_mint, read at initialization
+    pub tier_mode: TierMode,           // How price tier limits are read
+    #[max_len(MAX_PRICE_TIERS)]
+    pub price_tiers: Vec<PriceTier>, // Tiers before the base price, empty for flat pricing
+    pub auction: Option<DutchAuction>, // Descending price replacing the base price and tiers
+    pub last_price: u64,               // Lowest auction price paid so far
     pub start_ts: i64,                 // Sale start timestamp
     pub end_ts: i64,                   // Sale end timestamp
     pub total_allocated: u64,          // Total tokens allocated
+    pub max_per_wallet: Option<u64>,   // Sale-wide cap on any buyer's total, None for no cap
     pub sold: u64,                     // Tokens sold so far
+    pub claimed: u64,                  // Sold tokens claimed by buyers
+    pub tge_ts: i64,                   // Purchased tokens claimable from
+    pub guardian: Pubkey,              // Key allowed to pause the sale besides the owner
+    pub paused: bool,                  // Purchases blocked while set
+    pub claims_paused: bool,           // Purchase claims blocked while set
+    pub soft_cap: Option<u64>,         // Minimum raise in lamports, None for no refunds
+    pub raised: u64,                   // Total payments received
+    pub hard_cap_payment: Option<u64>, // Maximum raise in payment units, None for no cap
+    pub completed: bool,               // Token or payment cap reached, no more purchases
+    pub finalized: bool,               // finalize_sale has run
+    pub refunding: bool,               // Soft cap missed, buyers can refund
     pub bump: u8,                      // PDA bump seed
+    pub vault_bump: u8,                // Sale vault PDA bump seed
+    pub payment_escrow_bump: u8,       // Payment escrow PDA bump seed
 }
 
 /// Vesting schedule account
 #[account]
 #[derive(InitSpace)]
 pub struct Vesting {
+    pub sale: Pubkey,          // Sale this vesting belongs to
     pub beneficiary: Pubkey,   // Who receives vested tokens
+    pub schedule_index: u64,   // Beneficiary's schedule number within the sale
+    pub token_mint: Pubkey,    // Token being vested
     pub total_amount: u64,     // Total tokens to vest
     pub released: u64,         // Tokens already released
     pub start_ts: i64,         // Vesting start timestamp
@@ -503,7 +2138,9 @@ pub struct Vesting {
     pub duration_seconds: u64, // Total vesting duration
     pub revocable: bool,       // Can owner revoke?
     pub revoked: bool,         // Has been revoked?
+    pub revoked_ts: i64,       // Vesting stops at this timestamp once revoked
     pub bump: u8,              // PDA bump seed
+    pub vault_bump: u8,        // Vesting vault PDA bump seed
 }
 
 /// Buyer escrow account (tracks allocation)
@@ -514,9 +2151,89 @@ pub struct BuyerEscrow {
     pub buyer: Pubkey,   // Buyer wallet
     pub allocation: u64, // Tokens allocated to buyer
     pub claimed: u64,    // Tokens claimed by buyer
+    pub paid: u64,       // Total payment, refundable if the soft cap is missed
     pub bump: u8,        // PDA bump seed
 }
 
+/// Redeemed voucher marker (one per buyer, sale and nonce)
+#[account]
+#[derive(InitSpace)]
+pub struct VoucherNonce {
+    pub sale: Pubkey,  // Sale the voucher was issued for
+    pub buyer: Pubkey, // Buyer who redeemed it
+    pub nonce: u64,    // Voucher nonce
+    pub bump: u8,      // PDA bump seed
+}
+
+// ============================================================================
+// Sale Parameters
+// ============================================================================
+
+/// Sale parameters passed to initialize_sale
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SaleParams {
+    pub price_lamports_per_token: u64, // Price numerator per whole token, lamports or payment mint units
+    pub price_denominator: u64,        // Denominator shared by every price in the sale
+    pub start_ts: i64,                 // Sale start timestamp
+    pub end_ts: i64,                   // Sale end timestamp
+    pub total_allocated: u64,          // Total tokens allocated
+    pub voucher_signer: Pubkey,        // Backend key that signs vouchers
+    pub tge_ts: i64,                   // Purchased tokens claimable from
+    pub max_per_wallet: Option<u64>,   // Sale-wide cap on any buyer's total, None for no cap
+    pub guardian: Pubkey,              // Key allowed to pause the sale besides the owner
+    pub soft_cap: Option<u64>,         // Minimum raise in lamports, SOL sales only
+    pub hard_cap_payment: Option<u64>, // Maximum raise in payment units, None for no cap
+    pub tier_mode: TierMode,           // How price tier limits are read
+    pub price_tiers: Vec<PriceTier>,   // Up to MAX_PRICE_TIERS tiers, empty for flat pricing
+    pub auction: Option<DutchAuction>, // Dutch auction pricing, requires no price tiers
+    pub cluster: Cluster,              // Cluster the sale runs on, signed into vouchers
+}
+
+/// Sale parameters passed to update_sale, None leaves a field unchanged
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct UpdateSaleParams {
+    pub price_lamports_per_token: Option<u64>, // New price, only before the sale starts
+    pub start_ts: Option<i64>,                 // New start, only before the sale starts
+    pub end_ts: Option<i64>,                   // New end, can only be extended before it passes
+    pub total_allocated: Option<u64>,          // New allocation, not below sold, before the end
+    pub treasury: Option<Pubkey>,              // New treasury receiving payments
+}
+
+/// Longest an escrowed sale can be extended to run, so buyers' payments
+/// cannot be held in escrow indefinitely
+pub const MAX_ESCROW_SALE_DURATION: i64 = 90 * 86400;
+
+// ============================================================================
+// Price Tiers
+// ============================================================================
+
+/// Maximum number of price tiers a sale can hold
+pub const MAX_PRICE_TIERS: usize = 8;
+
+/// How the `limit` of each price tier is interpreted
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum TierMode {
+    Sold, // Tier applies while sold < limit; a purchase is split across tiers
+    Time, // Tier applies while the clock is before limit (unix timestamp)
+}
+
+/// One pricing tier; past the last tier the base price applies
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct PriceTier {
+    pub limit: u64,                    // Exclusive upper bound: sold amount or timestamp
+    pub price_lamports_per_token: u64, // Price numerator per whole token within this tier
+}
+
+/// Dutch auction: the price decays from start_price at start_ts to
+/// floor_price at end_ts
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, InitSpace)]
+pub struct DutchAuction {
+    pub start_price: u64,     // Price numerator per whole token at start_ts
+    pub floor_price: u64,     // Price numerator per whole token at end_ts
+    pub step_seconds: u64,    // Price drops every step_seconds, 0 for linear decay
+    pub clearing_price: bool, // Everyone pays the final price, SOL sales only
+}
+
 // ============================================================================
 // Voucher Data Structure
 // ============================================================================
@@ -531,12 +2248,643 @@ pub struct VoucherData {
     pub expiry_ts: i64,      // Voucher expiry timestamp
 }
 
+/// Cluster a sale runs on, fixed at initialization
+/// The same program ID and sale address can exist on several clusters, so
+/// vouchers sign the cluster to keep them from being replayed across them
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum Cluster {
+    MainnetBeta, // Tag 0
+    Devnet,      // Tag 1
+    Testnet,     // Tag 2
+    Localnet,    // Tag 3
+}
+
+/// Domain prefix of every signed voucher message
+pub const VOUCHER_DOMAIN: &[u8] = b"anchor-presale:voucher";
+/// Current voucher message layout; bump when the signed fields change
+pub const VOUCHER_VERSION: u8 = 1;
+
+impl VoucherData {
+    /// Message the backend signs for this voucher, built the same way by the
+    /// program and by off-chain signers depending on this crate
+    /// domain (22) + program id (32) + version (1) + cluster (1) + buyer (32)
+    /// + sale (32) + max_allocation (8) + nonce (8) + expiry_ts (8)
+    pub fn message(&self, cluster: Cluster) -> Vec<u8> {
+        let mut message = Vec::with_capacity(VOUCHER_DOMAIN.len() + 122);
+        message.extend_from_slice(VOUCHER_DOMAIN);
+        message.extend_from_slice(&crate::ID.to_bytes());
+        message.push(VOUCHER_VERSION);
+        message.push(cluster as u8);
+        message.extend_from_slice(&self.buyer.to_bytes());
+        message.extend_from_slice(&self.sale.to_bytes());
+        message.extend_from_slice(&self.max_allocation.to_le_bytes());
+        message.extend_from_slice(&self.nonce.to_le_bytes());
+        message.extend_from_slice(&self.expiry_ts.to_le_bytes());
+        message
+    }
+}
+
+// ============================================================================
+// Events
+// ============================================================================
+
+/// Emitted by update_sale with the values before and after the update
+#[event]
+pub struct SaleUpdated {
+    pub sale: Pubkey,
+    pub old_price_lamports_per_token: u64,
+    pub new_price_lamports_per_token: u64,
+    pub old_start_ts: i64,
+    pub new_start_ts: i64,
+    pub old_end_ts: i64,
+    pub new_end_ts: i64,
+    pub old_total_allocated: u64,
+    pub new_total_allocated: u64,
+    pub old_treasury: Pubkey,
+    pub new_treasury: Pubkey,
+    pub timestamp: i64,
+}
+
+/// Emitted by initialize_sale with the sale's starting configuration
+#[event]
+pub struct SaleInitialized {
+    pub sale: Pubkey,
+    pub owner: Pubkey,
+    pub sale_id: u64,
+    pub token_mint: Pubkey,
+    pub treasury: Pubkey,
+    pub payment_mint: Option<Pubkey>,
+    pub voucher_signer: Pubkey,
+    pub cluster: Cluster,
+    pub price_lamports_per_token: u64,
+    pub price_denominator: u64,
+    pub start_ts: i64,
+    pub end_ts: i64,
+    pub total_allocated: u64,
+    pub token_decimals: u8,
+    pub tge_ts: i64,
+    pub max_per_wallet: Option<u64>,
+    pub guardian: Pubkey,
+    pub soft_cap: Option<u64>,
+    pub hard_cap_payment: Option<u64>,
+    pub tier_mode: TierMode,
+    pub price_tiers: Vec<PriceTier>,
+    pub auction: Option<DutchAuction>,
+    pub timestamp: i64,
+}
+
+/// Emitted by rotate_voucher_signer
+#[event]
+pub struct VoucherSignerRotated {
+    pub sale: Pubkey,
+    pub old_voucher_signer: Pubkey,
+    pub new_voucher_signer: Pubkey,
+    pub timestamp: i64,
+}
+
+/// Emitted by set_merkle_root; `merkle_root` is None when the allowlist is cleared
+#[event]
+pub struct MerkleRootSet {
+    pub sale: Pubkey,
+    pub merkle_root: Option<[u8; 32]>,
+    pub timestamp: i64,
+}
+
+/// Emitted by propose_owner; ownership moves once `pending_owner` accepts
+#[event]
+pub struct OwnershipProposed {
+    pub sale: Pubkey,
+    pub owner: Pubkey,
+    pub pending_owner: Pubkey,
+    pub timestamp: i64,
+}
+
+/// Emitted by accept_owner
+#[event]
+pub struct OwnershipTransferred {
+    pub sale: Pubkey,
+    pub old_owner: Pubkey,
+    pub new_owner: Pubkey,
+    pub timestamp: i64,
+}
+
+/// Emitted by pause_sale; `claims_paused` is the claim state after the pause
+#[event]
+pub struct SalePaused {
+    pub sale: Pubkey,
+    pub authority: Pubkey,
+    pub claims_paused: bool,
+    pub timestamp: i64,
+}
+
+/// Emitted by resume_sale, which reopens purchases and claims
+#[event]
+pub struct SaleResumed {
+    pub sale: Pubkey,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+/// Emitted by every buy instruction; `payment_mint` is None for SOL purchases,
+/// `nonce` is None for allowlist purchases, `tier` is the allowlist tier and
+/// None for voucher purchases, and the buyer and sale totals are the values
+/// after the purchase
+#[event]
+pub struct TokensPurchased {
+    pub sale: Pubkey,
+    pub buyer: Pubkey,
+    pub payment_mint: Option<Pubkey>,
+    pub allocation: u64,
+    pub payment: u64,
+    pub nonce: Option<u64>,
+    pub tier: Option<u8>,
+    pub buyer_allocation: u64,
+    pub buyer_paid: u64,
+    pub sold: u64,
+    pub raised: u64,
+    pub timestamp: i64,
+}
+
+/// Emitted by fund_sale; `amount` is what the vault received, any transfer
+/// fee is paid by the owner on top
+#[event]
+pub struct SaleFunded {
+    pub sale: Pubkey,
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub vault_balance: u64,
+    pub timestamp: i64,
+}
+
+/// Emitted by withdraw_unsold; `outstanding` is what stays in the vault for
+/// buyers who have not claimed yet
+#[event]
+pub struct UnsoldWithdrawn {
+    pub sale: Pubkey,
+    pub owner: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+    pub outstanding: u64,
+    pub timestamp: i64,
+}
+
+/// Emitted by claim_purchased with the buyer and sale totals after the claim
+#[event]
+pub struct PurchaseClaimed {
+    pub sale: Pubkey,
+    pub buyer: Pubkey,
+    pub amount: u64,
+    pub buyer_allocation: u64,
+    pub buyer_claimed: u64,
+    pub claimed: u64,
+    pub timestamp: i64,
+}
+
+/// Emitted by finalize_sale; `proceeds` is what left the payment escrow for
+/// the treasury, zero when refunding
+#[event]
+pub struct SaleFinalized {
+    pub sale: Pubkey,
+    pub treasury: Pubkey,
+    pub proceeds: u64,
+    pub sold: u64,
+    pub raised: u64,
+    pub clearing_price: Option<u64>,
+    pub refunding: bool,
+    pub timestamp: i64,
+}
+
+/// Emitted by refund with the sale totals after the refund
+#[event]
+pub struct Refunded {
+    pub sale: Pubkey,
+    pub buyer: Pubkey,
+    pub amount: u64,
+    pub allocation: u64,
+    pub sold: u64,
+    pub raised: u64,
+    pub timestamp: i64,
+}
+
+/// Emitted by claim_rebate; `buyer_paid` is the buyer's cost at the
+/// clearing price after the rebate
+#[event]
+pub struct RebateClaimed {
+    pub sale: Pubkey,
+    pub buyer: Pubkey,
+    pub amount: u64,
+    pub clearing_price: u64,
+    pub buyer_allocation: u64,
+    pub buyer_paid: u64,
+    pub timestamp: i64,
+}
+
+/// Emitted by create_vesting with the new schedule
+#[event]
+pub struct VestingCreated {
+    pub sale: Pubkey,
+    pub vesting: Pubkey,
+    pub beneficiary: Pubkey,
+    pub token_mint: Pubkey,
+    pub schedule_index: u64,
+    pub total_amount: u64,
+    pub start_ts: i64,
+    pub cliff_seconds: u64,
+    pub duration_seconds: u64,
+    pub revocable: bool,
+    pub timestamp: i64,
+}
+
+/// Emitted by claim_vested; `released` is the schedule total after the claim
+#[event]
+pub struct VestingClaimed {
+    pub sale: Pubkey,
+    pub vesting: Pubkey,
+    pub beneficiary: Pubkey,
+    pub amount: u64,
+    pub released: u64,
+    pub total_amount: u64,
+    pub timestamp: i64,
+}
+
+/// Emitted by revoke_vesting with the unvested amount sent to `destination`
+#[event]
+pub struct VestingRevoked {
+    pub sale: Pubkey,
+    pub vesting: Pubkey,
+    pub beneficiary: Pubkey,
+    pub destination: Pubkey,
+    pub unvested: u64,
+    pub vested: u64,
+    pub released: u64,
+    pub timestamp: i64,
+}
+
 // ============================================================================
 // Helper Functions
 // ============================================================================
 
+/// Check sale timing, remaining supply and vault balance for a purchase
+fn validate_purchase(
+    sale_config: &SaleConfig,
+    sale_vault: &TokenAccount,
+    allocation: u64,
+    current_ts: i64,
+) -> Result<()> {
+    require!(!sale_config.paused, PresaleError::SalePaused);
+    require!(!sale_config.completed, PresaleError::SaleCompleted);
+
+    // Validate sale timing
+    require!(
+        current_ts >= sale_config.start_ts,
+        PresaleError::SaleNotStarted
+    );
+    require!(current_ts <= sale_config.end_ts, PresaleError::SaleEnded);
+    require!(allocation > 0, PresaleError::InvalidAllocation);
+
+    // Validate supply
+    let new_sold = sale_config
+        .sold
+        .checked_add(allocation)
+        .ok_or(PresaleError::Overflow)?;
+    require!(
+        new_sold <= sale_config.total_allocated,
+        PresaleError::InsufficientSupply
+    );
+
+    // Vault must hold every sold token that has not been claimed yet
+    let outstanding = new_sold
+        .checked_sub(sale_config.claimed)
+        .ok_or(PresaleError::Underflow)?;
+    require!(
+        outstanding <= sale_vault.amount,
+        PresaleError::InsufficientVaultBalance
+    );
+
+    Ok(())
+}
+
+/// Check that a voucher was issued to this buyer for this sale and that the
+/// buyer's running total stays within the voucher and per-wallet caps
+fn validate_voucher(
+    voucher: &VoucherData,
+    sale_config: &Account<SaleConfig>,
+    buyer: Pubkey,
+    purchased: u64,
+    allocation: u64,
+    current_ts: i64,
+) -> Result<()> {
+    require!(voucher.buyer == buyer, PresaleError::InvalidVoucher);
+    require!(
+        voucher.sale == sale_config.key(),
+        PresaleError::InvalidVoucher
+    );
+    require!(
+        voucher.expiry_ts >= current_ts,
+        PresaleError::VoucherExpired
+    );
+
+    validate_allocation_cap(sale_config, voucher.max_allocation, purchased, allocation)
+}
+
+/// Check a purchase against the buyer's max_allocation and the wallet cap
+/// max_allocation caps the buyer's lifetime total in this sale, not the
+/// single purchase, so it is checked against the escrow running total
+fn validate_allocation_cap(
+    sale_config: &SaleConfig,
+    max_allocation: u64,
+    purchased: u64,
+    allocation: u64,
+) -> Result<()> {
+    let total = purchased
+        .checked_add(allocation)
+        .ok_or(PresaleError::Overflow)?;
+    require!(total <= max_allocation, PresaleError::ExceedsAllocation);
+    if let Some(max_per_wallet) = sale_config.max_per_wallet {
+        require!(total <= max_per_wallet, PresaleError::ExceedsWalletCap);
+    }
+
+    Ok(())
+}
+
+/// Allowlist leaf: sha256(buyer (32) + max_allocation (8) + tier (1))
+fn allowlist_leaf(buyer: &Pubkey, max_allocation: u64, tier: u8) -> [u8; 32] {
+    hashv(&[buyer.as_ref(), &max_allocation.to_le_bytes(), &[tier]]).to_bytes()
+}
+
+/// Verify a merkle proof with sorted pair hashing, so proofs carry no
+/// left/right flags; leaves (41 bytes) and nodes (64 bytes) never collide
+fn verify_merkle_proof(proof: &[[u8; 32]], root: &[u8; 32], leaf: [u8; 32]) -> bool {
+    let computed = proof.iter().fold(leaf, |node, sibling| {
+        if node <= *sibling {
+            hashv(&[&node, sibling]).to_bytes()
+        } else {
+            hashv(&[sibling, &node]).to_bytes()
+        }
+    });
+    computed == *root
+}
+
+/// Payment owed for `allocation` token base units, in lamports or payment
+/// mint base units
+/// With sold tiers, the part of a purchase inside each tier pays that tier's price
+fn calculate_payment(sale_config: &SaleConfig, allocation: u64, current_ts: i64) -> Result<u64> {
+    if let Some(price) = auction_price(sale_config, current_ts)? {
+        return to_payment_units(
+            allocation as u128 * price as u128,
+            sale_config.price_denominator,
+            sale_config.token_decimals,
+        );
+    }
+
+    let base_price = sale_config.price_lamports_per_token as u128;
+    let mut remaining = allocation as u128;
+    // Sum of base units times price numerator, converted once at the end
+    let mut payment: u128 = 0;
+
+    match sale_config.tier_mode {
+        TierMode::Sold => {
+            let mut cursor = sale_config.sold as u128;
+            for tier in &sale_config.price_tiers {
+                if remaining == 0 {
+                    break;
+                }
+                let limit = tier.limit as u128;
+                if cursor >= limit {
+                    continue;
+                }
+                let take = remaining.min(limit - cursor);
+                payment = payment
+                    .checked_add(take * tier.price_lamports_per_token as u128)
+                    .ok_or(PresaleError::Overflow)?;
+                cursor += take;
+                remaining -= take;
+            }
+        }
+        TierMode::Time => {
+            if let Some(tier) = sale_config
+                .price_tiers
+                .iter()
+                .find(|tier| (current_ts as i128) < tier.limit as i128)
+            {
+                payment = remaining * tier.price_lamports_per_token as u128;
+                remaining = 0;
+            }
+        }
+    }
+
+    // Whatever is left falls past the last tier
+    payment = payment
+        .checked_add(remaining * base_price)
+        .ok_or(PresaleError::Overflow)?;
+
+    to_payment_units(
+        payment,
+        sale_config.price_denominator,
+        sale_config.token_decimals,
+    )
+}
+
+/// Convert `base units * price numerator` into payment units
+/// Divides by `price_denominator * 10^decimals` rounding up, so a buyer
+/// never pays less than the exact price
+fn to_payment_units(weighted: u128, price_denominator: u64, decimals: u8) -> Result<u64> {
+    let divisor = 10u128
+        .checked_pow(decimals as u32)
+        .and_then(|scale| scale.checked_mul(price_denominator as u128))
+        .ok_or(PresaleError::Overflow)?;
+    require!(divisor > 0, PresaleError::DivisionByZero);
+
+    Ok(u64::try_from(weighted.div_ceil(divisor)).map_err(|_| PresaleError::Overflow)?)
+}
+
+/// Current Dutch auction price, or None when the sale has no auction
+/// The decay is rounded down, so the price never undercuts the schedule
+fn auction_price(sale_config: &SaleConfig, current_ts: i64) -> Result<Option<u64>> {
+    let Some(auction) = &sale_config.auction else {
+        return Ok(None);
+    };
+
+    let duration = sale_config
+        .end_ts
+        .checked_sub(sale_config.start_ts)
+        .ok_or(PresaleError::Underflow)? as u128;
+    let mut elapsed = current_ts
+        .saturating_sub(sale_config.start_ts)
+        .clamp(0, duration as i64) as u128;
+    if auction.step_seconds > 0 {
+        let step = auction.step_seconds as u128;
+        elapsed = elapsed / step * step;
+    }
+
+    let decay = ((auction.start_price - auction.floor_price) as u128)
+        .checked_mul(elapsed)
+        .ok_or(PresaleError::Overflow)?
+        .checked_div(duration)
+        .ok_or(PresaleError::DivisionByZero)?;
+
+    Ok(Some(auction.start_price - decay as u64))
+}
+
+/// Record the auction price of a purchase as the sale's clearing price
+/// It only ever falls, so no buyer paid less than the final clearing price
+fn record_auction_price(sale_config: &mut SaleConfig, current_ts: i64) -> Result<()> {
+    if let Some(price) = auction_price(sale_config, current_ts)? {
+        sale_config.last_price = if sale_config.sold == 0 {
+            price
+        } else {
+            sale_config.last_price.min(price)
+        };
+    }
+
+    Ok(())
+}
+
+/// Whether payments wait in the payment escrow until finalize_sale
+fn escrows_payments(sale_config: &SaleConfig) -> bool {
+    sale_config.soft_cap.is_some() || clearing_enabled(sale_config)
+}
+
+/// Whether the sale is a Dutch auction settling at its final price
+fn clearing_enabled(sale_config: &SaleConfig) -> bool {
+    sale_config
+        .auction
+        .is_some_and(|auction| auction.clearing_price)
+}
+
+/// Cost of `amount` token base units at the auction's final price
+fn clearing_cost(sale_config: &SaleConfig, amount: u64) -> Result<u64> {
+    to_payment_units(
+        amount as u128 * sale_config.last_price as u128,
+        sale_config.price_denominator,
+        sale_config.token_decimals,
+    )
+}
+
+/// Check that price tiers fit the account and have strictly increasing limits
+fn validate_price_tiers(price_tiers: &[PriceTier]) -> Result<()> {
+    require!(
+        price_tiers.len() <= MAX_PRICE_TIERS,
+        PresaleError::InvalidPriceTiers
+    );
+    require!(
+        price_tiers
+            .windows(2)
+            .all(|pair| pair[0].limit < pair[1].limit),
+        PresaleError::InvalidPriceTiers
+    );
+
+    Ok(())
+}
+
+/// Check that a payment keeps the sale within its payment hard cap
+fn validate_payment(sale_config: &SaleConfig, payment: u64) -> Result<()> {
+    if let Some(hard_cap) = sale_config.hard_cap_payment {
+        let new_raised = sale_config
+            .raised
+            .checked_add(payment)
+            .ok_or(PresaleError::Overflow)?;
+        require!(new_raised <= hard_cap, PresaleError::HardCapExceeded);
+    }
+
+    Ok(())
+}
+
+/// Whether the sale has reached its token allocation or payment hard cap
+fn sale_completed(sale_config: &SaleConfig) -> bool {
+    sale_config.sold >= sale_config.total_allocated
+        || sale_config
+            .hard_cap_payment
+            .is_some_and(|hard_cap| sale_config.raised >= hard_cap)
+}
+
+/// Amount to send so that `amount` arrives after the mint's Token-2022
+/// transfer fee; classic SPL mints and mints without the extension pass through
+fn add_transfer_fee(mint: &InterfaceAccount<Mint>, amount: u64) -> Result<u64> {
+    let mint_info = mint.to_account_info();
+    if *mint_info.owner != spl_token_2022::ID {
+        return Ok(amount);
+    }
+
+    let mint_data = mint_info.try_borrow_data()?;
+    let mint_state = StateWithExtensions::<spl_token_2022::state::Mint>::unpack(&mint_data)?;
+    let Ok(fee_config) = mint_state.get_extension::<TransferFeeConfig>() else {
+        return Ok(amount);
+    };
+
+    let fee = fee_config
+        .calculate_inverse_epoch_fee(Clock::get()?.epoch, amount)
+        .ok_or(PresaleError::Overflow)?;
+
+    Ok(amount.checked_add(fee).ok_or(PresaleError::Overflow)?)
+}
+
+/// Record a redeemed voucher nonce, failing if it was already redeemed
+fn record_nonce(
+    voucher_nonce: &mut Account<VoucherNonce>,
+    voucher: &VoucherData,
+    bump: u8,
+) -> Result<()> {
+    // A fresh nonce account is zeroed; a redeemed one has its buyer set
+    require!(
+        voucher_nonce.buyer == Pubkey::default(),
+        PresaleError::VoucherAlreadyUsed
+    );
+    voucher_nonce.sale = voucher.sale;
+    voucher_nonce.buyer = voucher.buyer;
+    voucher_nonce.nonce = voucher.nonce;
+    voucher_nonce.bump = bump;
+
+    Ok(())
+}
+
+/// Record a paid purchase in the buyer escrow and the sale totals
+/// `payment` excludes any transfer fee, matching what the recipient received
+fn record_purchase(
+    sale_config: &mut Account<SaleConfig>,
+    buyer_escrow: &mut Account<BuyerEscrow>,
+    buyer: Pubkey,
+    allocation: u64,
+    payment: u64,
+    escrow_bump: u8,
+) -> Result<()> {
+    // First purchase creates the escrow; later vouchers add to it
+    if buyer_escrow.buyer == Pubkey::default() {
+        buyer_escrow.sale = sale_config.key();
+        buyer_escrow.buyer = buyer;
+        buyer_escrow.bump = escrow_bump;
+    }
+    buyer_escrow.allocation = buyer_escrow
+        .allocation
+        .checked_add(allocation)
+        .ok_or(PresaleError::Overflow)?;
+    buyer_escrow.paid = buyer_escrow
+        .paid
+        .checked_add(payment)
+        .ok_or(PresaleError::Overflow)?;
+
+    // Update sale sold and raised amounts
+    sale_config.sold = sale_config
+        .sold
+        .checked_add(allocation)
+        .ok_or(PresaleError::Overflow)?;
+    sale_config.raised = sale_config
+        .raised
+        .checked_add(payment)
+        .ok_or(PresaleError::Overflow)?;
+    sale_config.completed = sale_completed(sale_config);
+
+    Ok(())
+}
+
 /// Calculate vested amount based on time elapsed
+/// A revoked schedule stops vesting at its revoke timestamp
 fn calculate_vested_amount(vesting: &Vesting, current_ts: i64) -> Result<u64> {
+    let current_ts = if vesting.revoked {
+        current_ts.min(vesting.revoked_ts)
+    } else {
+        current_ts
+    };
     let elapsed = current_ts
         .checked_sub(vesting.start_ts)
         .ok_or(PresaleError::Underflow)?;
@@ -561,30 +2909,81 @@ fn calculate_vested_amount(vesting: &Vesting, current_ts: i64) -> Result<u64> {
     Ok(vested as u64)
 }
 
+/// Size of the Ed25519 instruction header (signature count + padding)
+const ED25519_HEADER_LEN: usize = 2;
+/// Size of one Ed25519SignatureOffsets entry
+const ED25519_OFFSETS_LEN: usize = 14;
+
 /// Verify ed25519 signature using Solana's ed25519 program
 /// This validates that the voucher was signed by the backend server
+///
+/// The Ed25519 precompile verifies the signature itself when the transaction
+/// is processed; here we only check that the instruction immediately before
+/// this one is an Ed25519Program instruction over exactly this public key,
+/// message and signature.
 fn verify_ed25519_signature(
+    instructions: &AccountInfo,
     message: &[u8],
-    _signature: &[u8; 64],
-    _public_key: &[u8; 32],
+    signature: &[u8; 64],
+    public_key: &[u8; 32],
 ) -> Result<()> {
-    // In production, you should use the ed25519 program instruction
-    // For now, we'll use a simplified check
-    //
-    // Production code should create an Ed25519 instruction and verify it:
-    // let ix = ed25519_instruction::new_ed25519_instruction(public_key, message, signature);
-    // And verify the instruction was included in the transaction
-
-    // For this implementation, we assume the signature is valid if the voucher_signer
-    // account is provided correctly. In production, add proper ed25519 verification.
-
-    msg!(
-        "Verifying ed25519 signature for message of length {}",
-        message.len()
+    let current_index = load_current_index_checked(instructions)?;
+    require!(current_index > 0, PresaleError::MissingEd25519Instruction);
+
+    let ix = load_instruction_at_checked((current_index - 1) as usize, instructions)
+        .map_err(|_| PresaleError::MissingEd25519Instruction)?;
+    require!(
+        ix.program_id == ed25519_program::ID,
+        PresaleError::MissingEd25519Instruction
+    );
+    require!(ix.accounts.is_empty(), PresaleError::InvalidEd25519Offsets);
+
+    let data = &ix.data;
+    require!(
+        data.len() >= ED25519_HEADER_LEN + ED25519_OFFSETS_LEN,
+        PresaleError::InvalidEd25519Offsets
+    );
+    // Exactly one signature, so the voucher cannot hide among others
+    require!(data[0] == 1, PresaleError::InvalidEd25519Offsets);
+
+    let read_u16 = |offset: usize| -> u16 { u16::from_le_bytes([data[offset], data[offset + 1]]) };
+    let offsets = ED25519_HEADER_LEN;
+    let signature_offset = read_u16(offsets) as usize;
+    let signature_ix_index = read_u16(offsets + 2);
+    let public_key_offset = read_u16(offsets + 4) as usize;
+    let public_key_ix_index = read_u16(offsets + 6);
+    let message_offset = read_u16(offsets + 8) as usize;
+    let message_size = read_u16(offsets + 10) as usize;
+    let message_ix_index = read_u16(offsets + 12);
+
+    // All data must live inside the Ed25519 instruction itself (u16::MAX),
+    // otherwise the precompile could be verifying bytes we never inspect
+    require!(
+        signature_ix_index == u16::MAX
+            && public_key_ix_index == u16::MAX
+            && message_ix_index == u16::MAX,
+        PresaleError::InvalidEd25519Offsets
     );
 
-    // TODO: Implement full ed25519 verification using solana_program::ed25519_program
-    // For now, this is a placeholder that should be replaced with actual verification
+    let ix_public_key = data
+        .get(public_key_offset..public_key_offset + 32)
+        .ok_or(PresaleError::InvalidEd25519Offsets)?;
+    let ix_signature = data
+        .get(signature_offset..signature_offset + 64)
+        .ok_or(PresaleError::InvalidEd25519Offsets)?;
+    let ix_message = data
+        .get(message_offset..message_offset + message_size)
+        .ok_or(PresaleError::InvalidEd25519Offsets)?;
+
+    require!(
+        ix_public_key == public_key,
+        PresaleError::InvalidVoucherSigner
+    );
+    require!(ix_message == message, PresaleError::InvalidVoucherMessage);
+    require!(
+        ix_signature == signature,
+        PresaleError::InvalidVoucherSignature
+    );
 
     Ok(())
 }
@@ -633,4 +3032,221 @@ pub enum PresaleError {
     AlreadyRevoked,
     #[msg("Unauthorized")]
     Unauthorized,
+    #[msg("Missing Ed25519 signature instruction before purchase")]
+    MissingEd25519Instruction,
+    #[msg("Voucher was not signed by the voucher signer")]
+    InvalidVoucherSigner,
+    #[msg("Signed message does not match voucher")]
+    InvalidVoucherMessage,
+    #[msg("Signature does not match voucher signature")]
+    InvalidVoucherSignature,
+    #[msg("Malformed Ed25519 instruction offsets")]
+    InvalidEd25519Offsets,
+    #[msg("Treasury does not match sale treasury")]
+    InvalidTreasury,
+    #[msg("Token claims have not opened yet")]
+    ClaimNotStarted,
+    #[msg("Sale vault does not hold enough tokens")]
+    InsufficientVaultBalance,
+    #[msg("Sale has not ended yet")]
+    SaleNotEnded,
+    #[msg("Payment mint does not match the sale's payment mint")]
+    InvalidPaymentMint,
+    #[msg("Purchase exceeds the sale's per-wallet cap")]
+    ExceedsWalletCap,
+    #[msg("Sale has already started")]
+    SaleAlreadyStarted,
+    #[msg("Sale is paused")]
+    SalePaused,
+    #[msg("Claims are paused")]
+    ClaimsPaused,
+    #[msg("Sale has already been finalized")]
+    SaleAlreadyFinalized,
+    #[msg("Sale has not been finalized")]
+    SaleNotFinalized,
+    #[msg("Sale did not reach its soft cap")]
+    SoftCapNotMet,
+    #[msg("Refunds are not enabled for this sale")]
+    RefundsNotEnabled,
+    #[msg("Sale has reached its cap")]
+    SaleCompleted,
+    #[msg("Payment exceeds the sale's hard cap")]
+    HardCapExceeded,
+    #[msg("Price tiers must be at most MAX_PRICE_TIERS with increasing limits")]
+    InvalidPriceTiers,
+    #[msg("Auction start price must be at least the floor and exclude price tiers")]
+    InvalidAuction,
+    #[msg("Sale does not settle at a clearing price")]
+    ClearingPriceNotEnabled,
+    #[msg("Price denominator must be greater than zero")]
+    InvalidPrice,
+    #[msg("Sale has no allowlist merkle root")]
+    MerkleRootNotSet,
+    #[msg("Invalid allowlist merkle proof")]
+    InvalidMerkleProof,
+    #[msg("Sale does not escrow payments")]
+    PaymentsNotEscrowed,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    proptest! {
+        #[test]
+        fn payment_rounds_up_by_less_than_one_unit(
+            amount: u64,
+            price: u64,
+            denominator in 1u64..,
+            decimals in 0u8..=18,
+        ) {
+            let weighted = amount as u128 * price as u128;
+            let divisor = denominator as u128 * 10u128.pow(decimals as u32);
+
+            match to_payment_units(weighted, denominator, decimals) {
+                Ok(payment) => {
+                    let payment = payment as u128;
+                    // Never below the exact price...
+                    prop_assert!(payment
+                        .checked_mul(divisor)
+                        .is_none_or(|paid| paid >= weighted));
+                    // ...and never a whole unit above it
+                    prop_assert!(payment == 0 || (payment - 1) * divisor < weighted);
+                }
+                Err(_) => prop_assert!(weighted.div_ceil(divisor) > u64::MAX as u128),
+            }
+        }
+
+        #[test]
+        fn payment_grows_with_amount(
+            a: u64,
+            b: u64,
+            price: u64,
+            denominator in 1u64..,
+            decimals in 0u8..=18,
+        ) {
+            let (low, high) = (a.min(b), a.max(b));
+            if let Ok(high_payment) =
+                to_payment_units(high as u128 * price as u128, denominator, decimals)
+            {
+                let low_payment =
+                    to_payment_units(low as u128 * price as u128, denominator, decimals).unwrap();
+                prop_assert!(low_payment <= high_payment);
+            }
+        }
+    }
+
+    #[test]
+    fn payment_handles_extreme_values() {
+        let max = u64::MAX as u128;
+
+        assert!(to_payment_units(max * max, 1, 0).is_err());
+        assert_eq!(to_payment_units(max * max, u64::MAX, 0).unwrap(), u64::MAX);
+        // u64::MAX / 1e18 = 18.44..., rounded up
+        assert_eq!(to_payment_units(max * max, u64::MAX, 18).unwrap(), 19);
+        assert_eq!(to_payment_units(1, u64::MAX, 18).unwrap(), 1);
+        assert_eq!(to_payment_units(0, 1, 18).unwrap(), 0);
+        // 1e15 base units of a 9 decimal mint at 1 SOL per token
+        assert_eq!(
+            to_payment_units(1_000_000_000_000_000 * 1_000_000_000, 1, 9).unwrap(),
+            1_000_000_000_000_000
+        );
+        assert!(to_payment_units(1, 1, 39).is_err());
+        assert!(to_payment_units(1, 0, 9).is_err());
+    }
+
+    #[test]
+    fn revoked_vesting_stops_at_revoke_time() {
+        let mut vesting = Vesting {
+            sale: Pubkey::default(),
+            beneficiary: Pubkey::default(),
+            schedule_index: 0,
+            token_mint: Pubkey::default(),
+            total_amount: 1_000,
+            released: 100,
+            start_ts: 0,
+            cliff_seconds: 0,
+            duration_seconds: 100,
+            revocable: true,
+            revoked: true,
+            revoked_ts: 40,
+            bump: 0,
+            vault_bump: 0,
+        };
+
+        assert_eq!(calculate_vested_amount(&vesting, 20).unwrap(), 200);
+        assert_eq!(calculate_vested_amount(&vesting, 40).unwrap(), 400);
+        assert_eq!(calculate_vested_amount(&vesting, 1_000).unwrap(), 400);
+
+        vesting.revoked = false;
+        assert_eq!(calculate_vested_amount(&vesting, 1_000).unwrap(), 1_000);
+    }
+
+    #[test]
+    fn merkle_proof_verifies_each_leaf() {
+        let leaves: Vec<[u8; 32]> = (0..3u8)
+            .map(|i| allowlist_leaf(&Pubkey::new_from_array([i; 32]), 1_000 * i as u64, i))
+            .collect();
+        let pair = |a: [u8; 32], b: [u8; 32]| {
+            let (low, high) = if a <= b { (a, b) } else { (b, a) };
+            hashv(&[&low, &high]).to_bytes()
+        };
+        // Odd leaf is promoted to the next level unchanged
+        let left = pair(leaves[0], leaves[1]);
+        let root = pair(left, leaves[2]);
+
+        assert!(verify_merkle_proof(
+            &[leaves[1], leaves[2]],
+            &root,
+            leaves[0]
+        ));
+        assert!(verify_merkle_proof(
+            &[leaves[0], leaves[2]],
+            &root,
+            leaves[1]
+        ));
+        assert!(verify_merkle_proof(&[left], &root, leaves[2]));
+
+        // A different allocation or tier is a different leaf
+        let inflated = allowlist_leaf(&Pubkey::new_from_array([1; 32]), 5_000, 1);
+        assert!(!verify_merkle_proof(
+            &[leaves[0], leaves[2]],
+            &root,
+            inflated
+        ));
+        let promoted = allowlist_leaf(&Pubkey::new_from_array([1; 32]), 1_000, 0);
+        assert!(!verify_merkle_proof(
+            &[leaves[0], leaves[2]],
+            &root,
+            promoted
+        ));
+    }
+
+    #[test]
+    fn voucher_message_is_domain_separated() {
+        let voucher = VoucherData {
+            buyer: Pubkey::new_from_array([1; 32]),
+            sale: Pubkey::new_from_array([2; 32]),
+            max_allocation: 3,
+            nonce: 4,
+            expiry_ts: -5,
+        };
+        let message = voucher.message(Cluster::Devnet);
+
+        assert_eq!(message.len(), 144);
+        assert!(message.starts_with(VOUCHER_DOMAIN));
+        assert_eq!(&message[22..54], crate::ID.as_ref());
+        assert_eq!(message[54], VOUCHER_VERSION);
+        assert_eq!(message[55], 1);
+        assert_eq!(&message[56..88], &[1; 32]);
+        assert_eq!(&message[88..120], &[2; 32]);
+        assert_eq!(&message[120..128], &3u64.to_le_bytes());
+        assert_eq!(&message[128..136], &4u64.to_le_bytes());
+        assert_eq!(&message[136..], &(-5i64).to_le_bytes());
+
+        // Same voucher on another cluster signs different bytes
+        assert_ne!(message, voucher.message(Cluster::MainnetBeta));
+        assert_eq!(voucher.message(Cluster::MainnetBeta)[55], 0);
+    }
 }
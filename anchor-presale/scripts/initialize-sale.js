@@ -5,14 +5,15 @@
  * It creates the sale configuration with pricing, timing, and allocation parameters.
  * 
  * Usage:
- *   node scripts/initialize-sale.js
+ *   node scripts/initialize-sale.js --token-mint <pubkey>
  * 
  * Or with custom parameters:
- *   node scripts/initialize-sale.js --price 0.1 --start-days 0 --end-days 30 --allocation 1000000
+ *   node scripts/initialize-sale.js --token-mint <pubkey> --sale-id 1 --price 0.1 --start-days 0 --end-days 30 --allocation 1000000
  */
 
 const anchor = require('@coral-xyz/anchor');
 const { PublicKey, Keypair, SystemProgram, Connection, clusterApiUrl } = require('@solana/web3.js');
+const { getMint } = require('@solana/spl-token');
 const fs = require('fs');
 const path = require('path');
 const os = require('os');
@@ -24,6 +25,14 @@ const idl = JSON.parse(
 
 const PROGRAM_ID = new PublicKey(idl.address || idl.metadata.address);
 
+// Cluster names accepted by --cluster, mapped to the program's Cluster enum
+const CLUSTERS = {
+  'mainnet-beta': { mainnetBeta: {} },
+  devnet: { devnet: {} },
+  testnet: { testnet: {} },
+  localnet: { localnet: {} },
+};
+
 // Parse command line arguments
 function parseArgs() {
   const args = process.argv.slice(2);
@@ -32,8 +41,12 @@ function parseArgs() {
     startDaysFromNow: 0, // Default: starts immediately
     endDaysFromNow: 30, // Default: ends in 30 days
     allocationTokens: 1_000_000, // Default: 1M tokens
-    tokenMint: null, // Will use default if not provided
+    saleId: 0, // Default: the owner's first sale
+    cluster: 'devnet', // Cluster signed into vouchers
+    tokenMint: null, // Required
     treasury: null, // Will use wallet if not provided
+    voucherSigner: null, // Will use wallet if not provided
+    guardian: null, // Will use wallet if not provided
   };
 
   for (let i = 0; i < args.length; i++) {
@@ -55,6 +68,18 @@ function parseArgs() {
     } else if (args[i] === '--treasury' && args[i + 1]) {
       config.treasury = new PublicKey(args[i + 1]);
       i++;
+    } else if (args[i] === '--sale-id' && args[i + 1]) {
+      config.saleId = parseInt(args[i + 1]);
+      i++;
+    } else if (args[i] === '--cluster' && args[i + 1]) {
+      config.cluster = args[i + 1];
+      i++;
+    } else if (args[i] === '--voucher-signer' && args[i + 1]) {
+      config.voucherSigner = new PublicKey(args[i + 1]);
+      i++;
+    } else if (args[i] === '--guardian' && args[i + 1]) {
+      config.guardian = new PublicKey(args[i + 1]);
+      i++;
     }
   }
 
@@ -68,14 +93,22 @@ async function main() {
   // Parse configuration
   const config = parseArgs();
 
+  if (!config.tokenMint) {
+    console.error('❌ --token-mint is required; the sale vault is created for that mint');
+    process.exit(1);
+  }
+  if (!CLUSTERS[config.cluster]) {
+    console.error('❌ Unknown cluster:', config.cluster);
+    process.exit(1);
+  }
+
   console.log('Configuration:');
   console.log(`  Price: ${config.priceSolPerToken} SOL per token`);
   console.log(`  Start: ${config.startDaysFromNow} days from now`);
   console.log(`  End: ${config.endDaysFromNow} days from now`);
   console.log(`  Allocation: ${config.allocationTokens.toLocaleString()} tokens`);
-  if (config.tokenMint) {
-    console.log(`  Token Mint: ${config.tokenMint.toBase58()}`);
-  }
+  console.log(`  Sale ID: ${config.saleId}`);
+  console.log(`  Token Mint: ${config.tokenMint.toBase58()}`);
   if (config.treasury) {
     console.log(`  Treasury: ${config.treasury.toBase58()}`);
   }
@@ -120,8 +153,21 @@ async function main() {
   // Initialize program
   const program = new anchor.Program(idl, provider);
 
-  // Convert price: SOL per token -> lamports per token (assuming 9 decimals)
-  const priceLamportsPerToken = new anchor.BN(config.priceSolPerToken * 1e9);
+  // Look up the mint and the token program that owns it (SPL Token or Token-2022)
+  const tokenMint = config.tokenMint;
+  const mintAccount = await connection.getAccountInfo(tokenMint);
+  if (!mintAccount) {
+    console.error('❌ Token mint not found:', tokenMint.toBase58());
+    process.exit(1);
+  }
+  const tokenProgram = mintAccount.owner;
+  const mint = await getMint(connection, tokenMint, 'confirmed', tokenProgram);
+
+  // Price is lamports per whole token
+  const priceLamportsPerToken = new anchor.BN(Math.round(config.priceSolPerToken * 1e9));
+  const totalAllocated = new anchor.BN(config.allocationTokens).mul(
+    new anchor.BN(10).pow(new anchor.BN(mint.decimals))
+  );
 
   // Calculate timestamps
   const now = Math.floor(Date.now() / 1000);
@@ -129,18 +175,35 @@ async function main() {
   const endTs = new anchor.BN(now + config.endDaysFromNow * 24 * 60 * 60);
 
   // Set defaults
-  const tokenMint = config.tokenMint || new PublicKey('So11111111111111111111111111111111111111112'); // Default to native SOL mint
   const treasury = config.treasury || wallet.publicKey;
+  const voucherSigner = config.voucherSigner || wallet.publicKey;
+  const guardian = config.guardian || wallet.publicKey;
 
-  // Derive the sale config PDA (must include owner's public key)
+  // Derive the sale PDAs (the sale config is keyed by owner and sale id)
+  const saleId = new anchor.BN(config.saleId);
   const [saleConfig] = PublicKey.findProgramAddressSync(
-    [Buffer.from('sale_config'), wallet.publicKey.toBuffer()],
+    [
+      Buffer.from('sale_config'),
+      wallet.publicKey.toBuffer(),
+      saleId.toArrayLike(Buffer, 'le', 8),
+    ],
+    program.programId
+  );
+  const [saleVault] = PublicKey.findProgramAddressSync(
+    [Buffer.from('sale_vault'), saleConfig.toBuffer()],
+    program.programId
+  );
+  const [paymentEscrow] = PublicKey.findProgramAddressSync(
+    [Buffer.from('payment_escrow'), saleConfig.toBuffer()],
     program.programId
   );
 
   console.log('📝 Sale Config PDA:', saleConfig.toBase58());
   console.log('   Token Mint:', tokenMint.toBase58());
+  console.log('   Sale Vault:', saleVault.toBase58());
   console.log('   Treasury:', treasury.toBase58());
+  console.log('   Voucher Signer:', voucherSigner.toBase58());
+  console.log('   Guardian:', guardian.toBase58());
   console.log('   Owner:', wallet.publicKey.toBase58());
   console.log('');
 
@@ -154,18 +217,34 @@ async function main() {
   try {
     console.log('🔄 Sending transaction...');
 
+    // SOL-priced sale with flat pricing, no caps and claims opening at sale end
     const tx = await program.methods
-      .initializeSale(
+      .initializeSale(saleId, {
         priceLamportsPerToken,
+        priceDenominator: new anchor.BN(1),
         startTs,
         endTs,
-        new anchor.BN(config.allocationTokens * 1e9) // Convert to smallest units (9 decimals)
-      )
+        totalAllocated,
+        voucherSigner,
+        tgeTs: endTs,
+        maxPerWallet: null,
+        guardian,
+        softCap: null,
+        hardCapPayment: null,
+        tierMode: { sold: {} },
+        priceTiers: [],
+        auction: null,
+        cluster: CLUSTERS[config.cluster],
+      })
       .accounts({
         saleConfig: saleConfig,
-        owner: wallet.publicKey,
         tokenMint: tokenMint,
+        saleVault: saleVault,
         treasury: treasury,
+        paymentEscrow: paymentEscrow,
+        paymentMint: null,
+        owner: wallet.publicKey,
+        tokenProgram: tokenProgram,
         systemProgram: SystemProgram.programId,
       })
       .rpc();
@@ -184,29 +263,33 @@ async function main() {
     const saleConfigAccount = await program.account.saleConfig.fetch(saleConfig);
     console.log('📊 Sale Config Account:');
     console.log('   Owner:', saleConfigAccount.owner.toBase58());
-    console.log('   Token Mint:', saleConfigAccount.token_mint.toBase58());
+    const unit = 10 ** mint.decimals;
+    console.log('   Token Mint:', saleConfigAccount.tokenMint.toBase58());
     console.log('   Treasury:', saleConfigAccount.treasury.toBase58());
-    console.log('   Price:', saleConfigAccount.price_lamports_per_token.toString(), 'lamports per token');
-    console.log('   Start Time:', new Date(saleConfigAccount.start_ts.toNumber() * 1000).toISOString());
-    console.log('   End Time:', new Date(saleConfigAccount.end_ts.toNumber() * 1000).toISOString());
-    console.log('   Total Allocation:', (saleConfigAccount.total_allocated.toNumber() / 1e9).toLocaleString(), 'tokens');
-    console.log('   Sold Amount:', (saleConfigAccount.sold.toNumber() / 1e9).toLocaleString(), 'tokens');
+    console.log('   Price:', saleConfigAccount.priceLamportsPerToken.toString(), 'lamports per token');
+    console.log('   Start Time:', new Date(saleConfigAccount.startTs.toNumber() * 1000).toISOString());
+    console.log('   End Time:', new Date(saleConfigAccount.endTs.toNumber() * 1000).toISOString());
+    console.log('   Total Allocation:', (saleConfigAccount.totalAllocated.toNumber() / unit).toLocaleString(), 'tokens');
+    console.log('   Sold Amount:', (saleConfigAccount.sold.toNumber() / unit).toLocaleString(), 'tokens');
     console.log('');
 
     // Save config to file
     const outputPath = path.join(__dirname, '../sale-config.json');
     const outputData = {
       saleConfigAddress: saleConfig.toBase58(),
+      saleId: config.saleId,
+      saleVault: saleVault.toBase58(),
       programId: program.programId.toBase58(),
       owner: wallet.publicKey.toBase58(),
       tokenMint: tokenMint.toBase58(),
       treasury: treasury.toBase58(),
       priceSolPerToken: config.priceSolPerToken,
-      startTime: new Date(saleConfigAccount.start_ts.toNumber() * 1000).toISOString(),
-      endTime: new Date(saleConfigAccount.end_ts.toNumber() * 1000).toISOString(),
+      startTime: new Date(saleConfigAccount.startTs.toNumber() * 1000).toISOString(),
+      endTime: new Date(saleConfigAccount.endTs.toNumber() * 1000).toISOString(),
       totalAllocation: config.allocationTokens,
       transactionSignature: tx,
       network: 'devnet',
+      cluster: config.cluster,
     };
 
     fs.writeFileSync(outputPath, JSON.stringify(outputData, null, 2));
@@ -217,10 +300,11 @@ async function main() {
     console.log('🎉 Sale initialization complete!');
     console.log('');
     console.log('📋 Next steps:');
-    console.log('1. Copy the Sale Config Address above');
-    console.log('2. Open the React app at http://localhost:3001');
-    console.log('3. Enter the Sale Config Address in the app');
-    console.log('4. Test the purchase flow with Phantom wallet');
+    console.log('1. Fund the sale vault with fund_sale');
+    console.log('2. Copy the Sale Config Address above');
+    console.log('3. Open the React app at http://localhost:3001');
+    console.log('4. Enter the Sale Config Address in the app');
+    console.log('5. Test the purchase flow with Phantom wallet');
     console.log('═'.repeat(60));
 
   } catch (error) {
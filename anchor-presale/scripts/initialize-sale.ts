@@ -8,12 +8,13 @@
  *   ts-node scripts/initialize-sale.ts
  * 
  * Or with custom parameters:
- *   ts-node scripts/initialize-sale.ts --price 0.1 --start-days 0 --end-days 30 --allocation 1000000
+ *   ts-node scripts/initialize-sale.ts --token-mint <pubkey> --price 0.1 --start-days 0 --end-days 30 --allocation 1000000
  */
 
 import * as anchor from '@coral-xyz/anchor';
 import { Program, AnchorProvider, Wallet, BN } from '@coral-xyz/anchor';
 import { PublicKey, Keypair, SystemProgram, Connection, clusterApiUrl } from '@solana/web3.js';
+import { getMint } from '@solana/spl-token';
 import * as fs from 'fs';
 import * as path from 'path';
 
@@ -30,8 +31,12 @@ interface SaleConfig {
   startDaysFromNow: number;      // Days from now when sale starts (0 = now)
   endDaysFromNow: number;        // Days from now when sale ends (e.g., 30 days)
   totalAllocatedTokens: number;  // Total tokens allocated for sale (e.g., 1,000,000)
-  tokenMint?: string;            // Optional: Token mint address (use a placeholder if tokens not minted yet)
+  saleId: number;                // Distinguishes this sale from the owner's other sales
+  cluster: string;               // Cluster signed into vouchers: mainnet-beta, devnet, testnet or localnet
+  tokenMint?: string;            // Token mint address (required, the sale vault is created for it)
   treasury?: string;             // Optional: Treasury wallet address (defaults to payer)
+  voucherSigner?: string;        // Optional: Backend voucher signer (defaults to payer)
+  guardian?: string;             // Optional: Key allowed to pause the sale (defaults to payer)
 }
 
 const DEFAULT_CONFIG: SaleConfig = {
@@ -39,6 +44,16 @@ const DEFAULT_CONFIG: SaleConfig = {
   startDaysFromNow: 0,           // Start immediately
   endDaysFromNow: 30,            // End in 30 days
   totalAllocatedTokens: 1000000, // 1 million tokens
+  saleId: 0,                     // Owner's first sale
+  cluster: 'devnet',
+};
+
+// Cluster names accepted by --cluster, mapped to the program's Cluster enum
+const CLUSTERS: Record<string, object> = {
+  'mainnet-beta': { mainnetBeta: {} },
+  devnet: { devnet: {} },
+  testnet: { testnet: {} },
+  localnet: { localnet: {} },
 };
 
 /**
@@ -71,6 +86,18 @@ function parseArgs(): SaleConfig {
       case '--treasury':
         config.treasury = value;
         break;
+      case '--sale-id':
+        config.saleId = parseInt(value);
+        break;
+      case '--cluster':
+        config.cluster = value;
+        break;
+      case '--voucher-signer':
+        config.voucherSigner = value;
+        break;
+      case '--guardian':
+        config.guardian = value;
+        break;
       case '--help':
         printHelp();
         process.exit(0);
@@ -95,19 +122,23 @@ Options:
   --start-days <number>   Days from now when sale starts (default: 0)
   --end-days <number>     Days from now when sale ends (default: 30)
   --allocation <number>   Total tokens allocated for sale (default: 1000000)
-  --token-mint <pubkey>   Token mint address (default: placeholder)
+  --token-mint <pubkey>   Token mint address (required)
   --treasury <pubkey>     Treasury wallet address (default: payer wallet)
+  --sale-id <number>      Sale id, unique per owner (default: 0)
+  --cluster <name>        mainnet-beta, devnet, testnet or localnet (default: devnet)
+  --voucher-signer <pubkey>  Backend voucher signer (default: payer wallet)
+  --guardian <pubkey>     Key allowed to pause the sale (default: payer wallet)
   --help                  Show this help message
 
 Examples:
   # Initialize with defaults
-  ts-node scripts/initialize-sale.ts
+  ts-node scripts/initialize-sale.ts --token-mint <pubkey>
 
   # Custom pricing and duration
-  ts-node scripts/initialize-sale.ts --price 0.05 --end-days 60
+  ts-node scripts/initialize-sale.ts --token-mint <pubkey> --price 0.05 --end-days 60
 
   # Full configuration
-  ts-node scripts/initialize-sale.ts --price 0.2 --start-days 1 --end-days 45 --allocation 2000000
+  ts-node scripts/initialize-sale.ts --token-mint <pubkey> --sale-id 1 --price 0.2 --start-days 1 --end-days 45 --allocation 2000000
   `);
 }
 
@@ -137,6 +168,15 @@ async function initializeSale() {
   // Parse configuration
   const config = parseArgs();
 
+  if (!config.tokenMint) {
+    console.error('❌ --token-mint is required; the sale vault is created for that mint');
+    process.exit(1);
+  }
+  if (!CLUSTERS[config.cluster]) {
+    console.error('❌ Unknown cluster:', config.cluster);
+    process.exit(1);
+  }
+
   // Setup connection and wallet
   const connection = new Connection(
     process.env.ANCHOR_PROVIDER_URL || clusterApiUrl('devnet'),
@@ -167,32 +207,55 @@ async function initializeSale() {
   // Initialize program
   const program = new Program(idl, provider) as Program<typeof idl>;
 
-  // Convert price: SOL per token -> lamports per token (assuming 9 decimals)
-  // If token has 9 decimals and costs 0.1 SOL, that's 0.1 * 1e9 lamports per 1 * 1e9 smallest units
-  // So price_lamports_per_token = 0.1 * 1e9 / 1e9 = 0.1 lamports per smallest unit
-  // Actually, we want: lamports per full token (with decimals)
-  const priceLamportsPerToken = new BN(config.priceSolPerToken * 1e9);
+  // Token mint, and the token program that owns it (SPL Token or Token-2022)
+  const tokenMint = new PublicKey(config.tokenMint);
+  const mintAccount = await connection.getAccountInfo(tokenMint);
+  if (!mintAccount) {
+    console.error('❌ Token mint not found:', tokenMint.toBase58());
+    process.exit(1);
+  }
+  const tokenProgram = mintAccount.owner;
+  const mint = await getMint(connection, tokenMint, 'confirmed', tokenProgram);
+
+  // Price is lamports per whole token
+  const priceLamportsPerToken = new BN(Math.round(config.priceSolPerToken * 1e9));
 
   // Calculate timestamps
   const now = Math.floor(Date.now() / 1000);
   const startTs = new BN(now + config.startDaysFromNow * 24 * 60 * 60);
   const endTs = new BN(now + config.endDaysFromNow * 24 * 60 * 60);
 
-  // Total allocated (assuming 9 decimals, multiply by 1e9)
-  const totalAllocated = new BN(config.totalAllocatedTokens).mul(new BN(1e9));
-
-  // Token mint and treasury
-  const tokenMint = config.tokenMint 
-    ? new PublicKey(config.tokenMint)
-    : Keypair.generate().publicKey; // Placeholder if not minted yet
+  // Total allocated in base units of the mint
+  const totalAllocated = new BN(config.totalAllocatedTokens).mul(
+    new BN(10).pow(new BN(mint.decimals))
+  );
 
   const treasury = config.treasury
     ? new PublicKey(config.treasury)
     : wallet.publicKey;
+  const voucherSigner = config.voucherSigner
+    ? new PublicKey(config.voucherSigner)
+    : wallet.publicKey;
+  const guardian = config.guardian
+    ? new PublicKey(config.guardian)
+    : wallet.publicKey;
 
-  // Derive sale config PDA
-  const [saleConfigPDA, bump] = await PublicKey.findProgramAddress(
-    [Buffer.from('sale_config'), wallet.publicKey.toBuffer()],
+  // Derive sale PDAs; the sale config is keyed by creator and sale id
+  const saleId = new BN(config.saleId);
+  const [saleConfigPDA] = PublicKey.findProgramAddressSync(
+    [
+      Buffer.from('sale_config'),
+      wallet.publicKey.toBuffer(),
+      saleId.toArrayLike(Buffer, 'le', 8),
+    ],
+    program.programId
+  );
+  const [saleVaultPDA] = PublicKey.findProgramAddressSync(
+    [Buffer.from('sale_vault'), saleConfigPDA.toBuffer()],
+    program.programId
+  );
+  const [paymentEscrowPDA] = PublicKey.findProgramAddressSync(
+    [Buffer.from('payment_escrow'), saleConfigPDA.toBuffer()],
     program.programId
   );
 
@@ -204,25 +267,44 @@ async function initializeSale() {
   console.log('  Total Allocation:', config.totalAllocatedTokens.toLocaleString(), 'tokens');
   console.log('  Token Mint:', tokenMint.toBase58());
   console.log('  Treasury:', treasury.toBase58());
+  console.log('  Voucher Signer:', voucherSigner.toBase58());
+  console.log('  Guardian:', guardian.toBase58());
+  console.log('  Sale ID:', saleId.toString());
   console.log('  Sale Config PDA:', saleConfigPDA.toBase58());
   console.log('');
 
   try {
     console.log('⏳ Sending transaction...');
 
-    // Call initialize_sale instruction
+    // Call initialize_sale instruction: a SOL-priced sale with flat pricing,
+    // no caps and claims opening when the sale ends
     const tx = await program.methods
-      .initializeSale(
+      .initializeSale(saleId, {
         priceLamportsPerToken,
+        priceDenominator: new BN(1),
         startTs,
         endTs,
-        totalAllocated
-      )
+        totalAllocated,
+        voucherSigner,
+        tgeTs: endTs,
+        maxPerWallet: null,
+        guardian,
+        softCap: null,
+        hardCapPayment: null,
+        tierMode: { sold: {} },
+        priceTiers: [],
+        auction: null,
+        cluster: CLUSTERS[config.cluster],
+      })
       .accounts({
         saleConfig: saleConfigPDA,
         tokenMint,
+        saleVault: saleVaultPDA,
         treasury,
+        paymentEscrow: paymentEscrowPDA,
+        paymentMint: null,
         owner: wallet.publicKey,
+        tokenProgram,
         systemProgram: SystemProgram.programId,
       })
       .rpc();
@@ -235,19 +317,23 @@ async function initializeSale() {
     console.log('');
     console.log('🔑 Important Information:');
     console.log('  Sale Config Address:', saleConfigPDA.toBase58());
+    console.log('  Sale Vault:', saleVaultPDA.toBase58());
     console.log('  Token Mint:', tokenMint.toBase58());
     console.log('  Treasury:', treasury.toBase58());
     console.log('');
     console.log('💡 Next Steps:');
-    console.log('  1. Update your React app with the Sale Config address');
-    console.log('  2. Test the purchase flow at http://localhost:3001');
-    console.log('  3. Ensure your Laravel backend is running on port 8000');
+    console.log('  1. Fund the sale vault with fund_sale');
+    console.log('  2. Update your React app with the Sale Config address');
+    console.log('  3. Test the purchase flow at http://localhost:3001');
+    console.log('  4. Ensure your Laravel backend is running on port 8000');
     console.log('');
 
     // Save config to file for reference
     const outputPath = path.join(__dirname, '../sale-config.json');
     fs.writeFileSync(outputPath, JSON.stringify({
       saleConfigAddress: saleConfigPDA.toBase58(),
+      saleId: config.saleId,
+      saleVault: saleVaultPDA.toBase58(),
       tokenMint: tokenMint.toBase58(),
       treasury: treasury.toBase58(),
       owner: wallet.publicKey.toBase58(),
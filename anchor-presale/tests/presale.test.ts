@@ -6,15 +6,28 @@ import {
   Keypair,
   SystemProgram,
   LAMPORTS_PER_SOL,
+  Transaction,
+  sendAndConfirmTransaction,
+  Ed25519Program,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
+  TransactionInstruction,
 } from "@solana/web3.js";
 import {
   TOKEN_PROGRAM_ID,
+  TOKEN_2022_PROGRAM_ID,
+  ASSOCIATED_TOKEN_PROGRAM_ID,
+  ExtensionType,
+  createInitializeMintInstruction,
+  createInitializeTransferFeeConfigInstruction,
+  getMintLen,
   createMint,
+  getAccount,
   getOrCreateAssociatedTokenAccount,
   mintTo,
 } from "@solana/spl-token";
 import { assert, expect } from "chai";
 import nacl from "tweetnacl";
+import { createHash } from "crypto";
 
 describe("anchor-presale", () => {
   // Configure the client to use the local cluster
@@ -27,23 +40,347 @@ describe("anchor-presale", () => {
   let saleOwner: Keypair;
   let buyer: Keypair;
   let voucherSigner: Keypair; // Backend voucher signer
+  let guardian: Keypair; // Can pause the sale alongside the owner
   let tokenMint: PublicKey;
   let treasury: Keypair;
   let saleConfigPda: PublicKey;
+  let saleVaultPda: PublicKey;
   let buyerEscrowPda: PublicKey;
   let vestingPda: PublicKey;
 
   // Sale parameters
-  const pricePerToken = new anchor.BN(1_000_000); // 0.001 SOL per token
+  const tokenDecimals = 9;
+  const pricePerToken = new anchor.BN(LAMPORTS_PER_SOL); // 1 SOL per whole token
   const totalAllocated = new anchor.BN(1_000_000); // 1M tokens
   let startTs: anchor.BN;
   let endTs: anchor.BN;
+  let tgeTs: anchor.BN;
+
+  // Message format: domain + program id (32) + version (1) + cluster (1)
+  // + buyer (32) + sale (32) + max_allocation (8) + nonce (8) + expiry_ts (8)
+  const voucherDomain = Buffer.from("anchor-presale:voucher");
+  const voucherVersion = 1;
+  const localnetCluster = 3; // Cluster::Localnet, which every test sale uses
+  const voucherMessage = (voucher: {
+    buyer: PublicKey;
+    sale: PublicKey;
+    maxAllocation: anchor.BN;
+    nonce: anchor.BN;
+    expiryTs: anchor.BN;
+  }): Buffer =>
+    Buffer.concat([
+      voucherDomain,
+      program.programId.toBuffer(),
+      Buffer.from([voucherVersion, localnetCluster]),
+      voucher.buyer.toBuffer(),
+      voucher.sale.toBuffer(),
+      voucher.maxAllocation.toArrayLike(Buffer, "le", 8),
+      voucher.nonce.toArrayLike(Buffer, "le", 8),
+      voucher.expiryTs.toArrayLike(Buffer, "le", 8),
+    ]);
+
+  // Ed25519Program instruction that must precede buy_with_voucher
+  const ed25519Ix = (
+    signer: Keypair,
+    message: Buffer,
+    signature: Uint8Array
+  ): TransactionInstruction =>
+    Ed25519Program.createInstructionWithPublicKey({
+      publicKey: signer.publicKey.toBytes(),
+      message,
+      signature,
+    });
+
+  const fundedKeypair = async (sol = 5): Promise<Keypair> => {
+    const keypair = Keypair.generate();
+    await provider.connection.requestAirdrop(
+      keypair.publicKey,
+      sol * LAMPORTS_PER_SOL
+    );
+    await new Promise((resolve) => setTimeout(resolve, 1000));
+    return keypair;
+  };
+
+  const saleConfigFor = (
+    owner: PublicKey,
+    saleId = new anchor.BN(0)
+  ): PublicKey =>
+    PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("sale_config"),
+        owner.toBuffer(),
+        saleId.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    )[0];
+
+  // Default sale parameters for initialize_sale, with optional overrides
+  const saleParams = (overrides: Record<string, any> = {}) => ({
+    priceLamportsPerToken: pricePerToken,
+    priceDenominator: new anchor.BN(1),
+    startTs,
+    endTs,
+    totalAllocated,
+    voucherSigner: voucherSigner.publicKey,
+    tgeTs,
+    maxPerWallet: null,
+    guardian: guardian.publicKey,
+    softCap: null,
+    hardCapPayment: null,
+    tierMode: { sold: {} },
+    priceTiers: [],
+    auction: null,
+    cluster: { localnet: {} },
+    ...overrides,
+  });
+
+  // Payment for base units * price numerator, rounded up like the program
+  const toPayment = (weighted: anchor.BN): anchor.BN => {
+    const scale = new anchor.BN(10).pow(new anchor.BN(tokenDecimals));
+    return weighted.add(scale.subn(1)).div(scale);
+  };
+
+  const paymentFor = (allocation: anchor.BN, price = pricePerToken) =>
+    toPayment(allocation.mul(price));
+
+  const saleVaultFor = (sale: PublicKey): PublicKey =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("sale_vault"), sale.toBuffer()],
+      program.programId
+    )[0];
+
+  const paymentEscrowFor = (sale: PublicKey): PublicKey =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("payment_escrow"), sale.toBuffer()],
+      program.programId
+    )[0];
+
+  const vestingFor = (
+    sale: PublicKey,
+    beneficiary: PublicKey,
+    scheduleIndex = new anchor.BN(0)
+  ): PublicKey =>
+    PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("vesting"),
+        sale.toBuffer(),
+        beneficiary.toBuffer(),
+        scheduleIndex.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    )[0];
+
+  const voucherNonceFor = (voucher: {
+    buyer: PublicKey;
+    sale: PublicKey;
+    nonce: anchor.BN;
+  }): PublicKey =>
+    PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("nonce"),
+        voucher.sale.toBuffer(),
+        voucher.buyer.toBuffer(),
+        voucher.nonce.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    )[0];
+
+  const buyerEscrowFor = (sale: PublicKey, buyerKey: PublicKey): PublicKey =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("buyer_escrow"), sale.toBuffer(), buyerKey.toBuffer()],
+      program.programId
+    )[0];
+
+  const vestingVaultFor = (vesting: PublicKey): PublicKey =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("vesting_vault"), vesting.toBuffer()],
+      program.programId
+    )[0];
+
+  // Mints sale tokens to the owner's token account and returns its address
+  const mintToOwner = async (
+    owner: Keypair,
+    amount: anchor.BN
+  ): Promise<PublicKey> => {
+    const ownerAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      owner,
+      tokenMint,
+      owner.publicKey
+    );
+    await mintTo(
+      provider.connection,
+      owner,
+      tokenMint,
+      ownerAta.address,
+      saleOwner,
+      BigInt(amount.toString())
+    );
+    return ownerAta.address;
+  };
+
+  // Treasury's sale token account, which receives revoked vesting tokens
+  const treasuryTokenAccountFor = async (payer: Keypair): Promise<PublicKey> =>
+    (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        payer,
+        tokenMint,
+        treasury.publicKey
+      )
+    ).address;
+
+  // Mints sale tokens to the owner and deposits them with fund_sale
+  const fundSale = async (
+    owner: Keypair,
+    sale: PublicKey,
+    amount: anchor.BN
+  ) => {
+    const ownerTokenAccount = await mintToOwner(owner, amount);
+
+    return program.methods
+      .fundSale(amount)
+      .accounts({
+        saleConfig: sale,
+        saleVault: saleVaultFor(sale),
+        ownerTokenAccount,
+        tokenMint,
+        owner: owner.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([owner])
+      .rpc();
+  };
+
+  // Initializes sale 0 of `owner`, paid in SOL, with saleParams(overrides)
+  // and funds its vault with total_allocated unless `fund` is false
+  const initSale = async (
+    owner: Keypair,
+    overrides: Record<string, any> = {},
+    { fund = true } = {}
+  ) => {
+    const sale = saleConfigFor(owner.publicKey);
+    const initTx = await program.methods
+      .initializeSale(new anchor.BN(0), saleParams(overrides))
+      .accounts({
+        saleConfig: sale,
+        tokenMint,
+        saleVault: saleVaultFor(sale),
+        treasury: treasury.publicKey,
+        paymentEscrow: paymentEscrowFor(sale),
+        paymentMint: null,
+        owner: owner.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([owner])
+      .rpc();
+    const fundTx = fund
+      ? await fundSale(owner, sale, totalAllocated)
+      : undefined;
+
+    return { owner, sale, initTx, fundTx };
+  };
+
+  // Settles an ended sale; anyone can call finalize_sale
+  const finalize = (sale: PublicKey) =>
+    program.methods
+      .finalizeSale()
+      .accounts({
+        saleConfig: sale,
+        paymentEscrow: paymentEscrowFor(sale),
+        treasury: treasury.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+
+  // Waits `seconds`, returning at once when that is not positive
+  const sleep = (seconds: number) =>
+    new Promise((resolve) =>
+      setTimeout(resolve, Math.max(0, seconds) * 1000)
+    );
+
+  // Signs a voucher with the backend signer and submits buy_with_voucher
+  const buyWithVoucher = async (
+    sale: PublicKey,
+    saleTreasury: PublicKey,
+    buyerKeypair: Keypair,
+    allocation: anchor.BN,
+    nonce: anchor.BN,
+    maxAllocation = allocation
+  ) => {
+    const voucherData = {
+      buyer: buyerKeypair.publicKey,
+      sale,
+      maxAllocation,
+      nonce,
+      expiryTs: new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+    };
+    const message = voucherMessage(voucherData);
+    const signature = nacl.sign.detached(message, voucherSigner.secretKey);
+
+    return program.methods
+      .buyWithVoucher(allocation, voucherData, Array.from(signature))
+      .accounts({
+        saleConfig: sale,
+        buyerEscrow: buyerEscrowFor(sale, buyerKeypair.publicKey),
+        voucherNonce: voucherNonceFor(voucherData),
+        saleVault: saleVaultFor(sale),
+        buyer: buyerKeypair.publicKey,
+        treasury: saleTreasury,
+        paymentEscrow: paymentEscrowFor(sale),
+        voucherSigner: voucherSigner.publicKey,
+        instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+        systemProgram: SystemProgram.programId,
+      })
+      .preInstructions([ed25519Ix(voucherSigner, message, signature)])
+      .signers([buyerKeypair])
+      .rpc();
+  };
+
+  // Buys with a fresh wallet and returns it with what its escrow recorded
+  // as paid
+  const buyAsNewBuyer = async (sale: PublicKey, allocation: number) => {
+    const newBuyer = await fundedKeypair(20);
+    await buyWithVoucher(
+      sale,
+      treasury.publicKey,
+      newBuyer,
+      new anchor.BN(allocation),
+      new anchor.BN(1)
+    );
+    const escrow = await program.account.buyerEscrow.fetch(
+      buyerEscrowFor(sale, newBuyer.publicKey)
+    );
+    return { buyer: newBuyer, paid: escrow.paid.toNumber() };
+  };
+
+  // Parses the program events logged by a transaction
+  const eventsIn = async (tx: string) => {
+    await provider.connection.confirmTransaction(tx, "confirmed");
+    const txInfo = await provider.connection.getTransaction(tx, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(
+      program.programId,
+      new anchor.BorshCoder(program.idl)
+    );
+    return [...parser.parseLogs(txInfo.meta.logMessages)];
+  };
+
+  const eventNamed = async (tx: string, name: string) => {
+    const event = (await eventsIn(tx)).find((e) => e.name === name);
+    assert.ok(event, `Expected ${name} event`);
+    return event.data;
+  };
 
   before(async () => {
     // Initialize test accounts
     saleOwner = Keypair.generate();
     buyer = Keypair.generate();
     voucherSigner = Keypair.generate(); // This simulates the backend server keypair
+    guardian = Keypair.generate();
     treasury = Keypair.generate();
 
     // Airdrop SOL to test accounts
@@ -69,7 +406,7 @@ describe("anchor-presale", () => {
       saleOwner,
       saleOwner.publicKey,
       null,
-      9 // 9 decimals
+      tokenDecimals
     );
 
     console.log("Token mint created:", tokenMint.toBase58());
@@ -81,12 +418,11 @@ describe("anchor-presale", () => {
     const now = Math.floor(Date.now() / 1000);
     startTs = new anchor.BN(now - 3600); // Started 1 hour ago
     endTs = new anchor.BN(now + 86400); // Ends in 24 hours
+    tgeTs = startTs; // Claims open immediately
 
     // Derive PDAs
-    [saleConfigPda] = PublicKey.findProgramAddressSync(
-      [Buffer.from("sale_config"), saleOwner.publicKey.toBuffer()],
-      program.programId
-    );
+    saleConfigPda = saleConfigFor(saleOwner.publicKey);
+    saleVaultPda = saleVaultFor(saleConfigPda);
 
     [buyerEscrowPda] = PublicKey.findProgramAddressSync(
       [
@@ -97,21 +433,22 @@ describe("anchor-presale", () => {
       program.programId
     );
 
-    [vestingPda] = PublicKey.findProgramAddressSync(
-      [Buffer.from("vesting"), buyer.publicKey.toBuffer()],
-      program.programId
-    );
+    vestingPda = vestingFor(saleConfigPda, buyer.publicKey);
   });
 
   it("Initializes the sale", async () => {
     try {
       const tx = await program.methods
-        .initializeSale(pricePerToken, startTs, endTs, totalAllocated)
+        .initializeSale(new anchor.BN(0), saleParams())
         .accounts({
           saleConfig: saleConfigPda,
           tokenMint: tokenMint,
+          saleVault: saleVaultPda,
           treasury: treasury.publicKey,
+          paymentEscrow: paymentEscrowFor(saleConfigPda),
+          paymentMint: null,
           owner: saleOwner.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
           systemProgram: SystemProgram.programId,
         })
         .signers([saleOwner])
@@ -125,6 +462,7 @@ describe("anchor-presale", () => {
       assert.ok(saleConfig.owner.equals(saleOwner.publicKey));
       assert.ok(saleConfig.tokenMint.equals(tokenMint));
       assert.ok(saleConfig.treasury.equals(treasury.publicKey));
+      assert.ok(saleConfig.voucherSigner.equals(voucherSigner.publicKey));
       assert.equal(
         saleConfig.priceLamportsPerToken.toString(),
         pricePerToken.toString()
@@ -136,6 +474,11 @@ describe("anchor-presale", () => {
         totalAllocated.toString()
       );
       assert.equal(saleConfig.sold.toString(), "0");
+      assert.equal(saleConfig.tgeTs.toString(), tgeTs.toString());
+
+      const vault = await getAccount(provider.connection, saleVaultPda);
+      assert.ok(vault.mint.equals(tokenMint));
+      assert.ok(vault.owner.equals(saleConfigPda));
 
       console.log("✓ Sale initialized successfully");
     } catch (error) {
@@ -144,6 +487,13 @@ describe("anchor-presale", () => {
     }
   });
 
+  it("Owner funds the sale vault", async () => {
+    await fundSale(saleOwner, saleConfigPda, totalAllocated);
+
+    const vault = await getAccount(provider.connection, saleVaultPda);
+    assert.equal(vault.amount.toString(), totalAllocated.toString());
+  });
+
   it("Buyer purchases tokens with valid voucher", async () => {
     const allocation = new anchor.BN(10_000); // Buy 10,000 tokens
     const nonce = new anchor.BN(1);
@@ -159,14 +509,7 @@ describe("anchor-presale", () => {
     };
 
     // Serialize voucher message for signing
-    // Message format: buyer (32) + sale (32) + max_allocation (8) + nonce (8) + expiry_ts (8)
-    const message = Buffer.concat([
-      buyer.publicKey.toBuffer(),
-      saleConfigPda.toBuffer(),
-      allocation.toArrayLike(Buffer, "le", 8),
-      nonce.toArrayLike(Buffer, "le", 8),
-      expiryTs.toArrayLike(Buffer, "le", 8),
-    ]);
+    const message = voucherMessage(voucherData);
 
     // Sign the voucher using the backend voucher signer keypair
     const signature = nacl.sign.detached(message, voucherSigner.secretKey);
@@ -187,11 +530,16 @@ describe("anchor-presale", () => {
         .accounts({
           saleConfig: saleConfigPda,
           buyerEscrow: buyerEscrowPda,
+          voucherNonce: voucherNonceFor(voucherData),
+          saleVault: saleVaultPda,
           buyer: buyer.publicKey,
           treasury: treasury.publicKey,
+          paymentEscrow: paymentEscrowFor(saleConfigPda),
           voucherSigner: voucherSigner.publicKey,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
           systemProgram: SystemProgram.programId,
         })
+        .preInstructions([ed25519Ix(voucherSigner, message, signature)])
         .signers([buyer])
         .rpc();
 
@@ -212,7 +560,7 @@ describe("anchor-presale", () => {
       const treasuryBalanceAfter = await provider.connection.getBalance(
         treasury.publicKey
       );
-      const expectedPayment = allocation.mul(pricePerToken).toNumber();
+      const expectedPayment = paymentFor(allocation).toNumber();
       const actualPayment = treasuryBalanceAfter - treasuryBalanceBefore;
 
       assert.equal(actualPayment, expectedPayment);
@@ -232,11 +580,13 @@ describe("anchor-presale", () => {
     const cliffSeconds = new anchor.BN(3600); // 1 hour cliff
     const durationSeconds = new anchor.BN(86400); // 24 hour total vesting
     const revocable = true;
+    const ownerTokenAccount = await mintToOwner(saleOwner, vestingAmount);
 
     try {
       const tx = await program.methods
         .createVesting(
           buyer.publicKey,
+          new anchor.BN(0),
           vestingAmount,
           vestingStartTs,
           cliffSeconds,
@@ -246,7 +596,11 @@ describe("anchor-presale", () => {
         .accounts({
           saleConfig: saleConfigPda,
           vesting: vestingPda,
+          vestingVault: vestingVaultFor(vestingPda),
+          ownerTokenAccount,
+          tokenMint,
           owner: saleOwner.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
           systemProgram: SystemProgram.programId,
         })
         .signers([saleOwner])
@@ -256,7 +610,10 @@ describe("anchor-presale", () => {
 
       // Verify vesting account
       const vesting = await program.account.vesting.fetch(vestingPda);
+      assert.ok(vesting.sale.equals(saleConfigPda));
       assert.ok(vesting.beneficiary.equals(buyer.publicKey));
+      assert.equal(vesting.scheduleIndex.toString(), "0");
+      assert.ok(vesting.tokenMint.equals(tokenMint));
       assert.equal(vesting.totalAmount.toString(), vestingAmount.toString());
       assert.equal(vesting.released.toString(), "0");
       assert.equal(vesting.startTs.toString(), vestingStartTs.toString());
@@ -268,6 +625,14 @@ describe("anchor-presale", () => {
       assert.equal(vesting.revocable, revocable);
       assert.equal(vesting.revoked, false);
 
+      // Vault is funded with the full schedule on creation
+      const vault = await getAccount(
+        provider.connection,
+        vestingVaultFor(vestingPda)
+      );
+      assert.equal(vault.amount.toString(), vestingAmount.toString());
+      assert.ok(vault.owner.equals(vestingPda));
+
       console.log("✓ Vesting schedule created successfully");
     } catch (error) {
       console.error("Error creating vesting:", error);
@@ -358,46 +723,28 @@ describe("anchor-presale", () => {
       expiryTs: expiryTs,
     };
 
-    const message = Buffer.concat([
-      buyer.publicKey.toBuffer(),
-      saleConfigPda.toBuffer(),
-      allocation.toArrayLike(Buffer, "le", 8),
-      nonce.toArrayLike(Buffer, "le", 8),
-      expiryTs.toArrayLike(Buffer, "le", 8),
-    ]);
+    const message = voucherMessage(voucherData);
 
     const signature = nacl.sign.detached(message, voucherSigner.secretKey);
     const signatureArray = Array.from(signature);
 
-    // Create a new buyer for this test to avoid escrow conflict
-    const newBuyer = Keypair.generate();
-    await provider.connection.requestAirdrop(
-      newBuyer.publicKey,
-      5 * LAMPORTS_PER_SOL
-    );
-    await new Promise((resolve) => setTimeout(resolve, 1000));
-
-    const [newBuyerEscrowPda] = PublicKey.findProgramAddressSync(
-      [
-        Buffer.from("buyer_escrow"),
-        saleConfigPda.toBuffer(),
-        newBuyer.publicKey.toBuffer(),
-      ],
-      program.programId
-    );
-
     try {
       await program.methods
         .buyWithVoucher(allocation, voucherData, signatureArray)
         .accounts({
           saleConfig: saleConfigPda,
-          buyerEscrow: newBuyerEscrowPda,
-          buyer: newBuyer.publicKey,
+          buyerEscrow: buyerEscrowPda,
+          voucherNonce: voucherNonceFor(voucherData),
+          saleVault: saleVaultPda,
+          buyer: buyer.publicKey,
           treasury: treasury.publicKey,
+          paymentEscrow: paymentEscrowFor(saleConfigPda),
           voucherSigner: voucherSigner.publicKey,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
           systemProgram: SystemProgram.programId,
         })
-        .signers([newBuyer])
+        .preInstructions([ed25519Ix(voucherSigner, message, signature)])
+        .signers([buyer])
         .rpc();
 
       // Should not reach here
@@ -423,51 +770,3120 @@ describe("anchor-presale", () => {
       expiryTs: expiryTs,
     };
 
-    const message = Buffer.concat([
-      buyer.publicKey.toBuffer(),
-      saleConfigPda.toBuffer(),
-      maxAllocation.toArrayLike(Buffer, "le", 8),
-      nonce.toArrayLike(Buffer, "le", 8),
-      expiryTs.toArrayLike(Buffer, "le", 8),
-    ]);
+    const message = voucherMessage(voucherData);
 
     const signature = nacl.sign.detached(message, voucherSigner.secretKey);
     const signatureArray = Array.from(signature);
 
-    const newBuyer = Keypair.generate();
+    try {
+      await program.methods
+        .buyWithVoucher(attemptedAllocation, voucherData, signatureArray)
+        .accounts({
+          saleConfig: saleConfigPda,
+          buyerEscrow: buyerEscrowPda,
+          voucherNonce: voucherNonceFor(voucherData),
+          saleVault: saleVaultPda,
+          buyer: buyer.publicKey,
+          treasury: treasury.publicKey,
+          paymentEscrow: paymentEscrowFor(saleConfigPda),
+          voucherSigner: voucherSigner.publicKey,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          systemProgram: SystemProgram.programId,
+        })
+        .preInstructions([ed25519Ix(voucherSigner, message, signature)])
+        .signers([buyer])
+        .rpc();
+
+      assert.fail("Expected transaction to fail with exceeds allocation");
+    } catch (error) {
+      expect(error.toString()).to.include("ExceedsAllocation");
+      console.log("✓ Correctly rejected purchase exceeding voucher limit");
+    }
+  });
+
+  describe("voucher signature verification", () => {
+    const allocation = new anchor.BN(1_000);
+
+    // Fresh buyer with a voucher signed by the real voucher signer
+    const setup = async () => {
+      const newBuyer = Keypair.generate();
+      await provider.connection.requestAirdrop(
+        newBuyer.publicKey,
+        5 * LAMPORTS_PER_SOL
+      );
+      await new Promise((resolve) => setTimeout(resolve, 1000));
+
+      const [escrowPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("buyer_escrow"),
+          saleConfigPda.toBuffer(),
+          newBuyer.publicKey.toBuffer(),
+        ],
+        program.programId
+      );
+
+      const voucherData = {
+        buyer: newBuyer.publicKey,
+        sale: saleConfigPda,
+        maxAllocation: allocation,
+        nonce: new anchor.BN(100),
+        expiryTs: new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+      };
+      const message = voucherMessage(voucherData);
+      const signature = nacl.sign.detached(message, voucherSigner.secretKey);
+
+      return { newBuyer, escrowPda, voucherData, message, signature };
+    };
+
+    const buy = (
+      newBuyer: Keypair,
+      escrowPda: PublicKey,
+      voucherData: any,
+      signature: Uint8Array,
+      preInstructions: TransactionInstruction[]
+    ) =>
+      program.methods
+        .buyWithVoucher(allocation, voucherData, Array.from(signature))
+        .accounts({
+          saleConfig: saleConfigPda,
+          buyerEscrow: escrowPda,
+          voucherNonce: voucherNonceFor(voucherData),
+          saleVault: saleVaultPda,
+          buyer: newBuyer.publicKey,
+          treasury: treasury.publicKey,
+          paymentEscrow: paymentEscrowFor(saleConfigPda),
+          voucherSigner: voucherSigner.publicKey,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          systemProgram: SystemProgram.programId,
+        })
+        .preInstructions(preInstructions)
+        .signers([newBuyer])
+        .rpc();
+
+    it("Rejects purchase without an Ed25519 instruction", async () => {
+      const { newBuyer, escrowPda, voucherData, signature } = await setup();
+
+      try {
+        await buy(newBuyer, escrowPda, voucherData, signature, []);
+        assert.fail("Expected transaction to fail without Ed25519 instruction");
+      } catch (error) {
+        expect(error.toString()).to.include("MissingEd25519Instruction");
+      }
+    });
+
+    it("Rejects voucher signed by a different key", async () => {
+      const { newBuyer, escrowPda, voucherData, message } = await setup();
+      const attacker = Keypair.generate();
+      const forged = nacl.sign.detached(message, attacker.secretKey);
+
+      try {
+        await buy(newBuyer, escrowPda, voucherData, forged, [
+          ed25519Ix(attacker, message, forged),
+        ]);
+        assert.fail("Expected transaction to fail with wrong signer");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidVoucherSigner");
+      }
+    });
+
+    it("Rejects Ed25519 instruction over a different message", async () => {
+      const { newBuyer, escrowPda, voucherData } = await setup();
+      // Signer approved a smaller allocation than the voucher claims
+      const signedMessage = voucherMessage({
+        ...voucherData,
+        maxAllocation: new anchor.BN(1),
+      });
+      const signature = nacl.sign.detached(
+        signedMessage,
+        voucherSigner.secretKey
+      );
+
+      try {
+        await buy(newBuyer, escrowPda, voucherData, signature, [
+          ed25519Ix(voucherSigner, signedMessage, signature),
+        ]);
+        assert.fail("Expected transaction to fail with wrong message");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidVoucherMessage");
+      }
+    });
+
+    it("Rejects vouchers signed for another domain", async () => {
+      const { newBuyer, escrowPda, voucherData, message } = await setup();
+      const otherProgram = Keypair.generate().publicKey.toBuffer();
+      const domainLen = voucherDomain.length;
+      const forgedMessages = [
+        // Same fields signed for a program at another address
+        Buffer.concat([
+          message.subarray(0, domainLen),
+          otherProgram,
+          message.subarray(domainLen + 32),
+        ]),
+        // Same fields signed for a sale on another cluster (mainnet-beta)
+        Buffer.concat([
+          message.subarray(0, domainLen + 33),
+          Buffer.from([0]),
+          message.subarray(domainLen + 34),
+        ]),
+        // Unversioned layout: the fields alone
+        message.subarray(domainLen + 34),
+      ];
+
+      for (const forged of forgedMessages) {
+        const signature = nacl.sign.detached(forged, voucherSigner.secretKey);
+        try {
+          await buy(newBuyer, escrowPda, voucherData, signature, [
+            ed25519Ix(voucherSigner, forged, signature),
+          ]);
+          assert.fail("Expected a voucher for another domain to fail");
+        } catch (error) {
+          expect(error.toString()).to.include("InvalidVoucherMessage");
+        }
+      }
+    });
+
+    it("Rejects signature argument that differs from the Ed25519 instruction", async () => {
+      const { newBuyer, escrowPda, voucherData, message, signature } =
+        await setup();
+      const other = nacl.sign.detached(
+        Buffer.concat([message, Buffer.from([0])]),
+        voucherSigner.secretKey
+      );
+
+      try {
+        await buy(newBuyer, escrowPda, voucherData, other, [
+          ed25519Ix(voucherSigner, message, signature),
+        ]);
+        assert.fail("Expected transaction to fail with mismatched signature");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidVoucherSignature");
+      }
+    });
+
+    it("Rejects Ed25519 instruction with malformed offsets", async () => {
+      const { newBuyer, escrowPda, voucherData, signature } = await setup();
+      // Zero signatures is accepted by the precompile but carries no offsets
+      const emptyIx = new TransactionInstruction({
+        keys: [],
+        programId: Ed25519Program.programId,
+        data: Buffer.from([0, 0]),
+      });
+
+      try {
+        await buy(newBuyer, escrowPda, voucherData, signature, [emptyIx]);
+        assert.fail("Expected transaction to fail with malformed offsets");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidEd25519Offsets");
+      }
+    });
+
+    it("Rejects Ed25519 offsets that point at another instruction", async () => {
+      const { newBuyer, escrowPda, voucherData, message, signature } =
+        await setup();
+      const ix = ed25519Ix(voucherSigner, message, signature);
+      // signature_instruction_index = 0 (the Ed25519 instruction itself)
+      // instead of u16::MAX, which the program must refuse to interpret
+      ix.data.writeUInt16LE(0, 4);
+
+      try {
+        await buy(newBuyer, escrowPda, voucherData, signature, [ix]);
+        assert.fail("Expected transaction to fail with foreign offsets");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidEd25519Offsets");
+      }
+    });
+  });
+
+  describe("voucher signer rotation", () => {
+    it("Rejects voucher signed by a key that is not the sale's signer", async () => {
+      // Buyer signs their own voucher and passes their own key as signer
+      const newBuyer = Keypair.generate();
+      await provider.connection.requestAirdrop(
+        newBuyer.publicKey,
+        5 * LAMPORTS_PER_SOL
+      );
+      await new Promise((resolve) => setTimeout(resolve, 1000));
+
+      const [escrowPda] = PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("buyer_escrow"),
+          saleConfigPda.toBuffer(),
+          newBuyer.publicKey.toBuffer(),
+        ],
+        program.programId
+      );
+      const allocation = new anchor.BN(1_000);
+      const voucherData = {
+        buyer: newBuyer.publicKey,
+        sale: saleConfigPda,
+        maxAllocation: allocation,
+        nonce: new anchor.BN(200),
+        expiryTs: new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+      };
+      const message = voucherMessage(voucherData);
+      const signature = nacl.sign.detached(message, newBuyer.secretKey);
+
+      try {
+        await program.methods
+          .buyWithVoucher(allocation, voucherData, Array.from(signature))
+          .accounts({
+            saleConfig: saleConfigPda,
+            buyerEscrow: escrowPda,
+            voucherNonce: voucherNonceFor(voucherData),
+            saleVault: saleVaultPda,
+            buyer: newBuyer.publicKey,
+            treasury: treasury.publicKey,
+            paymentEscrow: paymentEscrowFor(saleConfigPda),
+            voucherSigner: newBuyer.publicKey,
+            instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+            systemProgram: SystemProgram.programId,
+          })
+          .preInstructions([ed25519Ix(newBuyer, message, signature)])
+          .signers([newBuyer])
+          .rpc();
+        assert.fail("Expected transaction to fail with self-signed voucher");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidVoucherSigner");
+      }
+    });
+
+    it("Rejects rotation by someone other than the owner", async () => {
+      const attacker = Keypair.generate();
+
+      try {
+        await program.methods
+          .rotateVoucherSigner(attacker.publicKey)
+          .accounts({
+            saleConfig: saleConfigPda,
+            owner: attacker.publicKey,
+          })
+          .signers([attacker])
+          .rpc();
+        assert.fail("Expected rotation by non-owner to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("ConstraintHasOne");
+      }
+    });
+
+    it("Owner rotates the voucher signer", async () => {
+      const newSigner = Keypair.generate();
+
+      await program.methods
+        .rotateVoucherSigner(newSigner.publicKey)
+        .accounts({
+          saleConfig: saleConfigPda,
+          owner: saleOwner.publicKey,
+        })
+        .signers([saleOwner])
+        .rpc();
+
+      let saleConfig = await program.account.saleConfig.fetch(saleConfigPda);
+      assert.ok(saleConfig.voucherSigner.equals(newSigner.publicKey));
+
+      // Restore the original signer for the remaining tests
+      await program.methods
+        .rotateVoucherSigner(voucherSigner.publicKey)
+        .accounts({
+          saleConfig: saleConfigPda,
+          owner: saleOwner.publicKey,
+        })
+        .signers([saleOwner])
+        .rpc();
+
+      saleConfig = await program.account.saleConfig.fetch(saleConfigPda);
+      assert.ok(saleConfig.voucherSigner.equals(voucherSigner.publicKey));
+    });
+  });
+
+  it("Rejects purchase paying a treasury other than the sale's", async () => {
+    // Attack: buyer routes the payment back to themselves while still
+    // recording an allocation against the sale
+    const attacker = Keypair.generate();
     await provider.connection.requestAirdrop(
-      newBuyer.publicKey,
+      attacker.publicKey,
       5 * LAMPORTS_PER_SOL
     );
     await new Promise((resolve) => setTimeout(resolve, 1000));
 
-    const [newBuyerEscrowPda] = PublicKey.findProgramAddressSync(
+    const [escrowPda] = PublicKey.findProgramAddressSync(
       [
         Buffer.from("buyer_escrow"),
         saleConfigPda.toBuffer(),
-        newBuyer.publicKey.toBuffer(),
+        attacker.publicKey.toBuffer(),
       ],
       program.programId
     );
+    const allocation = new anchor.BN(1_000);
+    const voucherData = {
+      buyer: attacker.publicKey,
+      sale: saleConfigPda,
+      maxAllocation: allocation,
+      nonce: new anchor.BN(300),
+      expiryTs: new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+    };
+    const message = voucherMessage(voucherData);
+    const signature = nacl.sign.detached(message, voucherSigner.secretKey);
 
     try {
       await program.methods
-        .buyWithVoucher(attemptedAllocation, voucherData, signatureArray)
+        .buyWithVoucher(allocation, voucherData, Array.from(signature))
         .accounts({
           saleConfig: saleConfigPda,
-          buyerEscrow: newBuyerEscrowPda,
-          buyer: newBuyer.publicKey,
-          treasury: treasury.publicKey,
+          buyerEscrow: escrowPda,
+          voucherNonce: voucherNonceFor(voucherData),
+          saleVault: saleVaultPda,
+          buyer: attacker.publicKey,
+          treasury: attacker.publicKey,
+          paymentEscrow: paymentEscrowFor(saleConfigPda),
           voucherSigner: voucherSigner.publicKey,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
           systemProgram: SystemProgram.programId,
         })
-        .signers([newBuyer])
+        .preInstructions([ed25519Ix(voucherSigner, message, signature)])
+        .signers([attacker])
         .rpc();
-
-      assert.fail("Expected transaction to fail with exceeds allocation");
+      assert.fail("Expected transaction to fail with wrong treasury");
     } catch (error) {
-      expect(error.toString()).to.include("ExceedsAllocation");
-      console.log("✓ Correctly rejected purchase exceeding voucher limit");
+      expect(error.toString()).to.include("InvalidTreasury");
     }
+
+    const escrow = await provider.connection.getAccountInfo(escrowPda);
+    assert.isNull(escrow, "No allocation may be recorded for the attacker");
+  });
+
+  describe("purchase claims", () => {
+    const claimAccounts = (
+      sale: PublicKey,
+      buyerKey: PublicKey,
+      buyerTokenAccount: PublicKey
+    ) => ({
+      saleConfig: sale,
+      buyerEscrow: buyerEscrowFor(sale, buyerKey),
+      saleVault: saleVaultFor(sale),
+      buyerTokenAccount,
+      tokenMint,
+      buyer: buyerKey,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+    });
+
+    it("Buyer claims purchased tokens after TGE", async () => {
+      const buyerAta = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        buyer,
+        tokenMint,
+        buyer.publicKey
+      );
+      const escrowBefore = await program.account.buyerEscrow.fetch(
+        buyerEscrowPda
+      );
+
+      await program.methods
+        .claimPurchased()
+        .accounts(
+          claimAccounts(saleConfigPda, buyer.publicKey, buyerAta.address)
+        )
+        .signers([buyer])
+        .rpc();
+
+      const escrow = await program.account.buyerEscrow.fetch(buyerEscrowPda);
+      assert.equal(escrow.claimed.toString(), escrowBefore.allocation.toString());
+
+      const account = await getAccount(provider.connection, buyerAta.address);
+      assert.equal(account.amount.toString(), escrow.allocation.toString());
+
+      const saleConfig = await program.account.saleConfig.fetch(saleConfigPda);
+      assert.equal(saleConfig.claimed.toString(), escrow.claimed.toString());
+    });
+
+    it("Rejects claiming more than the allocation", async () => {
+      const buyerAta = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        buyer,
+        tokenMint,
+        buyer.publicKey
+      );
+
+      try {
+        await program.methods
+          .claimPurchased()
+          .accounts(
+            claimAccounts(saleConfigPda, buyer.publicKey, buyerAta.address)
+          )
+          .signers([buyer])
+          .rpc();
+        assert.fail("Expected second claim to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("NothingToClaim");
+      }
+    });
+
+    it("Rejects claim before TGE", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      const { sale: otherSale } = await initSale(await fundedKeypair(), {
+        tgeTs: new anchor.BN(now + 7 * 86400), // TGE next week
+      });
+
+      const earlyBuyer = await fundedKeypair();
+      await buyWithVoucher(
+        otherSale,
+        treasury.publicKey,
+        earlyBuyer,
+        new anchor.BN(1_000),
+        new anchor.BN(1)
+      );
+      const buyerAta = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        earlyBuyer,
+        tokenMint,
+        earlyBuyer.publicKey
+      );
+
+      try {
+        await program.methods
+          .claimPurchased()
+          .accounts(
+            claimAccounts(otherSale, earlyBuyer.publicKey, buyerAta.address)
+          )
+          .signers([earlyBuyer])
+          .rpc();
+        assert.fail("Expected claim before TGE to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("ClaimNotStarted");
+      }
+    });
+  });
+
+  describe("sale vault", () => {
+    // Creates an unfunded sale owned by a fresh keypair
+    const newSale = async (saleStartTs: anchor.BN, saleEndTs: anchor.BN) =>
+      initSale(
+        await fundedKeypair(),
+        { startTs: saleStartTs, endTs: saleEndTs, tgeTs: saleStartTs },
+        { fund: false }
+      );
+
+    it("Rejects purchase beyond the vault balance", async () => {
+      const { owner, sale } = await newSale(startTs, endTs);
+      // Only part of total_allocated is deposited
+      await fundSale(owner, sale, new anchor.BN(500));
+
+      const newBuyer = await fundedKeypair();
+      try {
+        await buyWithVoucher(
+          sale,
+          treasury.publicKey,
+          newBuyer,
+          new anchor.BN(1_000),
+          new anchor.BN(1)
+        );
+        assert.fail("Expected purchase beyond vault balance to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("InsufficientVaultBalance");
+      }
+    });
+
+    it("Rejects withdrawing unsold tokens before the sale ends", async () => {
+      const ownerAta = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        saleOwner,
+        tokenMint,
+        saleOwner.publicKey
+      );
+
+      try {
+        await program.methods
+          .withdrawUnsold()
+          .accounts({
+            saleConfig: saleConfigPda,
+            saleVault: saleVaultPda,
+            ownerTokenAccount: ownerAta.address,
+            tokenMint,
+            owner: saleOwner.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([saleOwner])
+          .rpc();
+        assert.fail("Expected withdrawal before end to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("SaleNotEnded");
+      }
+    });
+
+    it("Owner withdraws unsold tokens after the sale ends", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      const { owner, sale } = await newSale(
+        new anchor.BN(now - 7200),
+        new anchor.BN(now - 3600)
+      );
+      await fundSale(owner, sale, totalAllocated);
+
+      const ownerAta = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        owner,
+        tokenMint,
+        owner.publicKey
+      );
+      const before = await getAccount(provider.connection, ownerAta.address);
+
+      await program.methods
+        .withdrawUnsold()
+        .accounts({
+          saleConfig: sale,
+          saleVault: saleVaultFor(sale),
+          ownerTokenAccount: ownerAta.address,
+          tokenMint,
+          owner: owner.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([owner])
+        .rpc();
+
+      const after = await getAccount(provider.connection, ownerAta.address);
+      assert.equal(
+        (after.amount - before.amount).toString(),
+        totalAllocated.toString()
+      );
+      const vault = await getAccount(provider.connection, saleVaultFor(sale));
+      assert.equal(vault.amount.toString(), "0");
+    });
+  });
+
+  describe("SPL token payments", () => {
+    const usdcPrice = new anchor.BN(2_000_000_000); // 2,000 USDC per whole token
+    let usdcMint: PublicKey;
+    let splOwner: Keypair;
+    let splSale: PublicKey;
+    let treasuryUsdc: PublicKey;
+
+    // Signs a voucher and submits buy_with_voucher_spl
+    const buyWithUsdc = async (
+      buyerKeypair: Keypair,
+      allocation: anchor.BN,
+      accounts: Partial<{
+        paymentMint: PublicKey;
+        buyerPaymentTokenAccount: PublicKey;
+        treasuryTokenAccount: PublicKey;
+      }> = {}
+    ) => {
+      const voucherData = {
+        buyer: buyerKeypair.publicKey,
+        sale: splSale,
+        maxAllocation: allocation,
+        nonce: new anchor.BN(1),
+        expiryTs: new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+      };
+      const message = voucherMessage(voucherData);
+      const signature = nacl.sign.detached(message, voucherSigner.secretKey);
+      const buyerUsdc = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        buyerKeypair,
+        usdcMint,
+        buyerKeypair.publicKey
+      );
+
+      return program.methods
+        .buyWithVoucherSpl(allocation, voucherData, Array.from(signature))
+        .accounts({
+          saleConfig: splSale,
+          buyerEscrow: buyerEscrowFor(splSale, buyerKeypair.publicKey),
+          voucherNonce: voucherNonceFor(voucherData),
+          saleVault: saleVaultFor(splSale),
+          buyer: buyerKeypair.publicKey,
+          paymentMint: usdcMint,
+          buyerPaymentTokenAccount: buyerUsdc.address,
+          treasuryTokenAccount: treasuryUsdc,
+          voucherSigner: voucherSigner.publicKey,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+          ...accounts,
+        })
+        .preInstructions([ed25519Ix(voucherSigner, message, signature)])
+        .signers([buyerKeypair])
+        .rpc();
+    };
+
+    // Fresh buyer holding `amount` USDC base units
+    const usdcBuyer = async (amount: number): Promise<Keypair> => {
+      const keypair = await fundedKeypair();
+      const ata = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        keypair,
+        usdcMint,
+        keypair.publicKey
+      );
+      await mintTo(
+        provider.connection,
+        keypair,
+        usdcMint,
+        ata.address,
+        saleOwner,
+        amount
+      );
+      return keypair;
+    };
+
+    before(async () => {
+      usdcMint = await createMint(
+        provider.connection,
+        saleOwner,
+        saleOwner.publicKey,
+        null,
+        6
+      );
+      treasuryUsdc = (
+        await getOrCreateAssociatedTokenAccount(
+          provider.connection,
+          saleOwner,
+          usdcMint,
+          treasury.publicKey
+        )
+      ).address;
+
+      splOwner = await fundedKeypair();
+      splSale = saleConfigFor(splOwner.publicKey);
+
+      await program.methods
+        .initializeSale(
+          new anchor.BN(0),
+          saleParams({ priceLamportsPerToken: usdcPrice })
+        )
+        .accounts({
+          saleConfig: splSale,
+          tokenMint,
+          saleVault: saleVaultFor(splSale),
+          treasury: treasury.publicKey,
+          paymentEscrow: paymentEscrowFor(splSale),
+          paymentMint: usdcMint,
+          owner: splOwner.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([splOwner])
+        .rpc();
+      await fundSale(splOwner, splSale, totalAllocated);
+    });
+
+    it("Records the payment mint on the sale", async () => {
+      const saleConfig = await program.account.saleConfig.fetch(splSale);
+      assert.ok(saleConfig.paymentMint.equals(usdcMint));
+    });
+
+    it("Buyer pays in USDC to the treasury token account", async () => {
+      const allocation = new anchor.BN(1_000);
+      const usdcBuyerKeypair = await usdcBuyer(10_000);
+      const before = await getAccount(provider.connection, treasuryUsdc);
+
+      await buyWithUsdc(usdcBuyerKeypair, allocation);
+
+      const after = await getAccount(provider.connection, treasuryUsdc);
+      assert.equal(
+        (after.amount - before.amount).toString(),
+        paymentFor(allocation, usdcPrice).toString()
+      );
+
+      const escrow = await program.account.buyerEscrow.fetch(
+        buyerEscrowFor(splSale, usdcBuyerKeypair.publicKey)
+      );
+      assert.equal(escrow.allocation.toString(), allocation.toString());
+    });
+
+    it("Rejects a payment token account of another mint", async () => {
+      const usdcBuyerKeypair = await usdcBuyer(10_000);
+      const otherMint = await createMint(
+        provider.connection,
+        saleOwner,
+        saleOwner.publicKey,
+        null,
+        6
+      );
+      const otherAta = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        usdcBuyerKeypair,
+        otherMint,
+        usdcBuyerKeypair.publicKey
+      );
+
+      try {
+        await buyWithUsdc(usdcBuyerKeypair, new anchor.BN(1_000), {
+          paymentMint: otherMint,
+          buyerPaymentTokenAccount: otherAta.address,
+        });
+        assert.fail("Expected purchase with another mint to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidPaymentMint");
+      }
+    });
+
+    it("Rejects a treasury token account not owned by the treasury", async () => {
+      const usdcBuyerKeypair = await usdcBuyer(10_000);
+      const buyerUsdc = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        usdcBuyerKeypair,
+        usdcMint,
+        usdcBuyerKeypair.publicKey
+      );
+
+      try {
+        await buyWithUsdc(usdcBuyerKeypair, new anchor.BN(1_000), {
+          treasuryTokenAccount: buyerUsdc.address,
+        });
+        assert.fail("Expected purchase paying the buyer to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidTreasury");
+      }
+    });
+
+    it("Rejects a SOL purchase on a USDC sale", async () => {
+      const solBuyer = await fundedKeypair();
+
+      try {
+        await buyWithVoucher(
+          splSale,
+          treasury.publicKey,
+          solBuyer,
+          new anchor.BN(1_000),
+          new anchor.BN(1)
+        );
+        assert.fail("Expected SOL purchase on a USDC sale to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidPaymentMint");
+      }
+    });
+  });
+
+  describe("Token-2022 with transfer fees", () => {
+    const feeBasisPoints = 100; // 1%
+    const maxFee = BigInt(1_000_000_000);
+    let feeMint: PublicKey;
+    let feeOwner: Keypair;
+    let feeSale: PublicKey;
+
+    // Fee the mint charges on a transfer of `amount` (rounded up, capped)
+    const transferFee = (amount: bigint): bigint => {
+      const fee =
+        (amount * BigInt(feeBasisPoints) + BigInt(9_999)) / BigInt(10_000);
+      return fee > maxFee ? maxFee : fee;
+    };
+
+    // Creates a Token-2022 mint with the transfer fee extension
+    const createFeeMint = async (decimals: number): Promise<PublicKey> => {
+      const mintKeypair = Keypair.generate();
+      const mintLen = getMintLen([ExtensionType.TransferFeeConfig]);
+      const lamports =
+        await provider.connection.getMinimumBalanceForRentExemption(mintLen);
+
+      const tx = new Transaction().add(
+        SystemProgram.createAccount({
+          fromPubkey: saleOwner.publicKey,
+          newAccountPubkey: mintKeypair.publicKey,
+          space: mintLen,
+          lamports,
+          programId: TOKEN_2022_PROGRAM_ID,
+        }),
+        createInitializeTransferFeeConfigInstruction(
+          mintKeypair.publicKey,
+          saleOwner.publicKey,
+          saleOwner.publicKey,
+          feeBasisPoints,
+          maxFee,
+          TOKEN_2022_PROGRAM_ID
+        ),
+        createInitializeMintInstruction(
+          mintKeypair.publicKey,
+          decimals,
+          saleOwner.publicKey,
+          null,
+          TOKEN_2022_PROGRAM_ID
+        )
+      );
+      await sendAndConfirmTransaction(provider.connection, tx, [
+        saleOwner,
+        mintKeypair,
+      ]);
+      return mintKeypair.publicKey;
+    };
+
+    // Token-2022 ATA for `owner`, optionally minted `amount` tokens
+    const feeAta = async (
+      mint: PublicKey,
+      owner: PublicKey,
+      payer: Keypair,
+      amount = BigInt(0),
+      allowOwnerOffCurve = false
+    ): Promise<PublicKey> => {
+      const ata = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        payer,
+        mint,
+        owner,
+        allowOwnerOffCurve,
+        undefined,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      );
+      if (amount > BigInt(0)) {
+        await mintTo(
+          provider.connection,
+          payer,
+          mint,
+          ata.address,
+          saleOwner,
+          amount,
+          [],
+          undefined,
+          TOKEN_2022_PROGRAM_ID
+        );
+      }
+      return ata.address;
+    };
+
+    const balance = async (account: PublicKey): Promise<bigint> =>
+      (
+        await getAccount(
+          provider.connection,
+          account,
+          undefined,
+          TOKEN_2022_PROGRAM_ID
+        )
+      ).amount;
+
+    before(async () => {
+      feeMint = await createFeeMint(9);
+      feeOwner = await fundedKeypair();
+      feeSale = saleConfigFor(feeOwner.publicKey);
+
+      await program.methods
+        .initializeSale(new anchor.BN(0), saleParams())
+        .accounts({
+          saleConfig: feeSale,
+          tokenMint: feeMint,
+          saleVault: saleVaultFor(feeSale),
+          treasury: treasury.publicKey,
+          paymentEscrow: paymentEscrowFor(feeSale),
+          paymentMint: null,
+          owner: feeOwner.publicKey,
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([feeOwner])
+        .rpc();
+    });
+
+    it("Owner covers the transfer fee when funding the vault", async () => {
+      const deposit = BigInt(totalAllocated.toString());
+      // Mint a little extra so the owner can pay the fee on top
+      const ownerAta = await feeAta(
+        feeMint,
+        feeOwner.publicKey,
+        feeOwner,
+        deposit * BigInt(2)
+      );
+
+      await program.methods
+        .fundSale(new anchor.BN(deposit.toString()))
+        .accounts({
+          saleConfig: feeSale,
+          saleVault: saleVaultFor(feeSale),
+          ownerTokenAccount: ownerAta,
+          tokenMint: feeMint,
+          owner: feeOwner.publicKey,
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+        })
+        .signers([feeOwner])
+        .rpc();
+
+      assert.equal(
+        (await balance(saleVaultFor(feeSale))).toString(),
+        deposit.toString()
+      );
+    });
+
+    it("Buyer claims a Token-2022 allocation", async () => {
+      const allocation = BigInt(10_000);
+      const feeBuyer = await fundedKeypair();
+      await buyWithVoucher(
+        feeSale,
+        treasury.publicKey,
+        feeBuyer,
+        new anchor.BN(allocation.toString()),
+        new anchor.BN(1)
+      );
+
+      const buyerAta = await feeAta(feeMint, feeBuyer.publicKey, feeBuyer);
+      const vaultBefore = await balance(saleVaultFor(feeSale));
+
+      await program.methods
+        .claimPurchased()
+        .accounts({
+          saleConfig: feeSale,
+          buyerEscrow: buyerEscrowFor(feeSale, feeBuyer.publicKey),
+          saleVault: saleVaultFor(feeSale),
+          buyerTokenAccount: buyerAta,
+          tokenMint: feeMint,
+          buyer: feeBuyer.publicKey,
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        })
+        .signers([feeBuyer])
+        .rpc();
+
+      // The whole allocation leaves the vault and the buyer bears the fee
+      assert.equal(
+        (vaultBefore - (await balance(saleVaultFor(feeSale)))).toString(),
+        allocation.toString()
+      );
+      assert.equal(
+        (await balance(buyerAta)).toString(),
+        (allocation - transferFee(allocation)).toString()
+      );
+      const escrow = await program.account.buyerEscrow.fetch(
+        buyerEscrowFor(feeSale, feeBuyer.publicKey)
+      );
+      assert.equal(escrow.claimed.toString(), allocation.toString());
+    });
+
+    it("Treasury receives the full price in a Token-2022 payment mint", async () => {
+      const price = new anchor.BN(2_000_000_000);
+      const allocation = new anchor.BN(1_000);
+      const payMint = await createFeeMint(6);
+      const payOwner = await fundedKeypair();
+      const paySale = saleConfigFor(payOwner.publicKey);
+
+      await program.methods
+        .initializeSale(
+          new anchor.BN(0),
+          saleParams({ priceLamportsPerToken: price })
+        )
+        .accounts({
+          saleConfig: paySale,
+          tokenMint,
+          saleVault: saleVaultFor(paySale),
+          treasury: treasury.publicKey,
+          paymentEscrow: paymentEscrowFor(paySale),
+          paymentMint: payMint,
+          owner: payOwner.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([payOwner])
+        .rpc();
+      await fundSale(payOwner, paySale, totalAllocated);
+
+      const payBuyer = await fundedKeypair();
+      const buyerPay = await feeAta(
+        payMint,
+        payBuyer.publicKey,
+        payBuyer,
+        BigInt(1_000_000)
+      );
+      const treasuryPay = await feeAta(payMint, treasury.publicKey, payBuyer);
+
+      const voucherData = {
+        buyer: payBuyer.publicKey,
+        sale: paySale,
+        maxAllocation: allocation,
+        nonce: new anchor.BN(1),
+        expiryTs: new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+      };
+      const message = voucherMessage(voucherData);
+      const signature = nacl.sign.detached(message, voucherSigner.secretKey);
+
+      await program.methods
+        .buyWithVoucherSpl(allocation, voucherData, Array.from(signature))
+        .accounts({
+          saleConfig: paySale,
+          buyerEscrow: buyerEscrowFor(paySale, payBuyer.publicKey),
+          voucherNonce: voucherNonceFor(voucherData),
+          saleVault: saleVaultFor(paySale),
+          buyer: payBuyer.publicKey,
+          paymentMint: payMint,
+          buyerPaymentTokenAccount: buyerPay,
+          treasuryTokenAccount: treasuryPay,
+          voucherSigner: voucherSigner.publicKey,
+          instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .preInstructions([ed25519Ix(voucherSigner, message, signature)])
+        .signers([payBuyer])
+        .rpc();
+
+      assert.equal(
+        (await balance(treasuryPay)).toString(),
+        paymentFor(allocation, price).toString()
+      );
+    });
+
+    it("Beneficiary claims vested Token-2022 tokens", async () => {
+      const beneficiary = await fundedKeypair();
+      const feeVestingPda = vestingFor(feeSale, beneficiary.publicKey);
+      const vault = vestingVaultFor(feeVestingPda);
+      const amount = BigInt(50_000);
+      const now = Math.floor(Date.now() / 1000);
+      const ownerAta = await feeAta(
+        feeMint,
+        feeOwner.publicKey,
+        feeOwner,
+        amount * BigInt(2)
+      );
+
+      // Fully vested: started two days ago over one day
+      await program.methods
+        .createVesting(
+          beneficiary.publicKey,
+          new anchor.BN(0),
+          new anchor.BN(amount.toString()),
+          new anchor.BN(now - 2 * 86400),
+          new anchor.BN(0),
+          new anchor.BN(86400),
+          false
+        )
+        .accounts({
+          saleConfig: feeSale,
+          vesting: feeVestingPda,
+          vestingVault: vault,
+          ownerTokenAccount: ownerAta,
+          tokenMint: feeMint,
+          owner: feeOwner.publicKey,
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([feeOwner])
+        .rpc();
+
+      // Owner covered the fee, so the vault holds the whole schedule
+      assert.equal((await balance(vault)).toString(), amount.toString());
+
+      const beneficiaryAta = await feeAta(
+        feeMint,
+        beneficiary.publicKey,
+        beneficiary
+      );
+
+      await program.methods
+        .claimVested()
+        .accounts({
+          vesting: feeVestingPda,
+          vestingVault: vault,
+          beneficiaryTokenAccount: beneficiaryAta,
+          tokenMint: feeMint,
+          beneficiary: beneficiary.publicKey,
+          tokenProgram: TOKEN_2022_PROGRAM_ID,
+        })
+        .signers([beneficiary])
+        .rpc();
+
+      assert.equal(
+        (await balance(beneficiaryAta)).toString(),
+        (amount - transferFee(amount)).toString()
+      );
+      const vesting = await program.account.vesting.fetch(feeVestingPda);
+      assert.equal(vesting.released.toString(), amount.toString());
+    });
+  });
+
+  describe("multiple sales per owner", () => {
+    const secondSaleId = new anchor.BN(1);
+    let secondSale: PublicKey;
+
+    it("Owner initializes a second sale with another sale_id", async () => {
+      secondSale = saleConfigFor(saleOwner.publicKey, secondSaleId);
+
+      await program.methods
+        .initializeSale(
+          secondSaleId,
+          saleParams({ priceLamportsPerToken: new anchor.BN(2_000_000) })
+        )
+        .accounts({
+          saleConfig: secondSale,
+          tokenMint,
+          saleVault: saleVaultFor(secondSale),
+          treasury: treasury.publicKey,
+          paymentEscrow: paymentEscrowFor(secondSale),
+          paymentMint: null,
+          owner: saleOwner.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([saleOwner])
+        .rpc();
+      await fundSale(saleOwner, secondSale, totalAllocated);
+
+      const saleConfig = await program.account.saleConfig.fetch(secondSale);
+      assert.equal(saleConfig.saleId.toString(), secondSaleId.toString());
+      assert.ok(saleConfig.owner.equals(saleOwner.publicKey));
+      assert.notOk(secondSale.equals(saleConfigPda));
+    });
+
+    it("Purchases in one sale do not touch the other", async () => {
+      const firstBefore = await program.account.saleConfig.fetch(saleConfigPda);
+      const allocation = new anchor.BN(2_000);
+
+      // Same buyer as the first sale gets a separate escrow here
+      await buyWithVoucher(
+        secondSale,
+        treasury.publicKey,
+        buyer,
+        allocation,
+        new anchor.BN(1)
+      );
+
+      const second = await program.account.saleConfig.fetch(secondSale);
+      assert.equal(second.sold.toString(), allocation.toString());
+      const firstAfter = await program.account.saleConfig.fetch(saleConfigPda);
+      assert.equal(firstAfter.sold.toString(), firstBefore.sold.toString());
+
+      const escrow = await program.account.buyerEscrow.fetch(
+        buyerEscrowFor(secondSale, buyer.publicKey)
+      );
+      assert.ok(escrow.sale.equals(secondSale));
+    });
+
+    it("Rejects reusing a sale_id", async () => {
+      try {
+        await program.methods
+          .initializeSale(secondSaleId, saleParams())
+          .accounts({
+            saleConfig: secondSale,
+            tokenMint,
+            saleVault: saleVaultFor(secondSale),
+            treasury: treasury.publicKey,
+            paymentEscrow: paymentEscrowFor(secondSale),
+            paymentMint: null,
+            owner: saleOwner.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([saleOwner])
+          .rpc();
+        assert.fail("Expected duplicate sale_id to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("already in use");
+      }
+    });
+  });
+
+  describe("vesting schedules per sale", () => {
+    const createSchedule = async (
+      owner: Keypair,
+      sale: PublicKey,
+      beneficiary: PublicKey,
+      scheduleIndex: anchor.BN
+    ) => {
+      const amount = new anchor.BN(5_000);
+      const ownerTokenAccount = await mintToOwner(owner, amount);
+      const vesting = vestingFor(sale, beneficiary, scheduleIndex);
+
+      return program.methods
+        .createVesting(
+          beneficiary,
+          scheduleIndex,
+          amount,
+          new anchor.BN(Math.floor(Date.now() / 1000)),
+          new anchor.BN(0),
+          new anchor.BN(86400),
+          true
+        )
+        .accounts({
+          saleConfig: sale,
+          vesting,
+          vestingVault: vestingVaultFor(vesting),
+          ownerTokenAccount,
+          tokenMint,
+          owner: owner.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([owner])
+        .rpc();
+    };
+
+    it("Creates several schedules for one beneficiary", async () => {
+      const advisor = Keypair.generate();
+      // Team grant and advisor grant in the same sale
+      await createSchedule(
+        saleOwner,
+        saleConfigPda,
+        advisor.publicKey,
+        new anchor.BN(0)
+      );
+      await createSchedule(
+        saleOwner,
+        saleConfigPda,
+        advisor.publicKey,
+        new anchor.BN(1)
+      );
+
+      for (const index of [0, 1]) {
+        const vesting = await program.account.vesting.fetch(
+          vestingFor(saleConfigPda, advisor.publicKey, new anchor.BN(index))
+        );
+        assert.ok(vesting.sale.equals(saleConfigPda));
+        assert.ok(vesting.beneficiary.equals(advisor.publicKey));
+        assert.equal(vesting.scheduleIndex.toString(), index.toString());
+      }
+    });
+
+    it("Rejects revoking a vesting from another owner's sale", async () => {
+      const { owner: otherOwner, sale: otherSale } = await initSale(
+        await fundedKeypair(),
+        {},
+        { fund: false }
+      );
+
+      const otherAta = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        otherOwner,
+        tokenMint,
+        otherOwner.publicKey
+      );
+
+      try {
+        // vestingPda belongs to saleConfigPda, not otherSale
+        await program.methods
+          .revokeVesting()
+          .accounts({
+            saleConfig: otherSale,
+            vesting: vestingPda,
+            vestingVault: vestingVaultFor(vestingPda),
+            treasuryTokenAccount: otherAta.address,
+            tokenMint,
+            owner: otherOwner.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([otherOwner])
+          .rpc();
+        assert.fail("Expected revoke from another sale to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("ConstraintSeeds");
+      }
+    });
+
+    it("Lets the beneficiary claim tokens vested before revocation", async () => {
+      const beneficiary = await fundedKeypair();
+      const amount = new anchor.BN(10_000);
+      const vesting = vestingFor(saleConfigPda, beneficiary.publicKey);
+      const vestingVault = vestingVaultFor(vesting);
+      const ownerTokenAccount = await mintToOwner(saleOwner, amount);
+
+      // Halfway through a one day schedule
+      await program.methods
+        .createVesting(
+          beneficiary.publicKey,
+          new anchor.BN(0),
+          amount,
+          new anchor.BN(Math.floor(Date.now() / 1000) - 43200),
+          new anchor.BN(0),
+          new anchor.BN(86400),
+          true
+        )
+        .accounts({
+          saleConfig: saleConfigPda,
+          vesting,
+          vestingVault,
+          ownerTokenAccount,
+          tokenMint,
+          owner: saleOwner.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([saleOwner])
+        .rpc();
+
+      const treasuryTokenAccount = await treasuryTokenAccountFor(saleOwner);
+      const treasuryBefore = await getAccount(
+        provider.connection,
+        treasuryTokenAccount
+      );
+      await program.methods
+        .revokeVesting()
+        .accounts({
+          saleConfig: saleConfigPda,
+          vesting,
+          vestingVault,
+          treasuryTokenAccount,
+          tokenMint,
+          owner: saleOwner.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([saleOwner])
+        .rpc();
+      const treasuryAfter = await getAccount(
+        provider.connection,
+        treasuryTokenAccount
+      );
+      const returned = Number(treasuryAfter.amount - treasuryBefore.amount);
+      assert.approximately(returned, 5_000, 10);
+
+      const beneficiaryAta = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        beneficiary,
+        tokenMint,
+        beneficiary.publicKey
+      );
+      const claim = () =>
+        program.methods
+          .claimVested()
+          .accounts({
+            vesting,
+            vestingVault,
+            beneficiaryTokenAccount: beneficiaryAta.address,
+            tokenMint,
+            beneficiary: beneficiary.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([beneficiary])
+          .rpc();
+      await claim();
+
+      // Vested and returned tokens account for the whole grant
+      const claimed = await getAccount(
+        provider.connection,
+        beneficiaryAta.address
+      );
+      assert.equal(Number(claimed.amount) + returned, amount.toNumber());
+      const vault = await getAccount(provider.connection, vestingVault);
+      assert.equal(vault.amount.toString(), "0");
+
+      // Nothing more vests after revocation
+      try {
+        await claim();
+        assert.fail("Expected a second claim after revocation to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("VestingRevoked");
+      }
+    });
+
+    it("Rejects revoking to an account the treasury does not own", async () => {
+      const beneficiary = Keypair.generate().publicKey;
+      const scheduleIndex = new anchor.BN(3);
+      await createSchedule(
+        saleOwner,
+        saleConfigPda,
+        beneficiary,
+        scheduleIndex
+      );
+      const vesting = vestingFor(saleConfigPda, beneficiary, scheduleIndex);
+      const ownerAta = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        saleOwner,
+        tokenMint,
+        saleOwner.publicKey
+      );
+
+      try {
+        await program.methods
+          .revokeVesting()
+          .accounts({
+            saleConfig: saleConfigPda,
+            vesting,
+            vestingVault: vestingVaultFor(vesting),
+            treasuryTokenAccount: ownerAta.address,
+            tokenMint,
+            owner: saleOwner.publicKey,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([saleOwner])
+          .rpc();
+        assert.fail("Expected revoke to the owner's account to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidTreasury");
+      }
+    });
+  });
+
+  it("Rejects claiming vested tokens from a vault the vesting does not own", async () => {
+    const foreignVault = await mintToOwner(buyer, new anchor.BN(10_000));
+    const buyerAta = await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      buyer,
+      tokenMint,
+      buyer.publicKey
+    );
+
+    try {
+      await program.methods
+        .claimVested()
+        .accounts({
+          vesting: vestingPda,
+          vestingVault: foreignVault,
+          beneficiaryTokenAccount: buyerAta.address,
+          tokenMint,
+          beneficiary: buyer.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([buyer])
+        .rpc();
+      assert.fail("Expected claim from a foreign vault to fail");
+    } catch (error) {
+      expect(error.toString()).to.include("ConstraintSeeds");
+    }
+  });
+
+  describe("voucher nonces", () => {
+    it("Buyer redeems a second voucher and the escrow accumulates", async () => {
+      const repeatBuyer = await fundedKeypair();
+      const escrowPda = buyerEscrowFor(saleConfigPda, repeatBuyer.publicKey);
+
+      await buyWithVoucher(
+        saleConfigPda,
+        treasury.publicKey,
+        repeatBuyer,
+        new anchor.BN(1_000),
+        new anchor.BN(1)
+      );
+      await buyWithVoucher(
+        saleConfigPda,
+        treasury.publicKey,
+        repeatBuyer,
+        new anchor.BN(500),
+        new anchor.BN(2),
+        new anchor.BN(1_500)
+      );
+
+      const escrow = await program.account.buyerEscrow.fetch(escrowPda);
+      assert.equal(escrow.allocation.toString(), "1500");
+      assert.ok(escrow.buyer.equals(repeatBuyer.publicKey));
+
+      const marker = await program.account.voucherNonce.fetch(
+        voucherNonceFor({
+          buyer: repeatBuyer.publicKey,
+          sale: saleConfigPda,
+          nonce: new anchor.BN(2),
+        })
+      );
+      assert.equal(marker.nonce.toString(), "2");
+    });
+
+    it("Rejects redeeming the same voucher twice", async () => {
+      const replayBuyer = await fundedKeypair();
+      const allocation = new anchor.BN(1_000);
+      const voucherData = {
+        buyer: replayBuyer.publicKey,
+        sale: saleConfigPda,
+        maxAllocation: allocation,
+        nonce: new anchor.BN(7),
+        expiryTs: new anchor.BN(Math.floor(Date.now() / 1000) + 3600),
+      };
+      const message = voucherMessage(voucherData);
+      const signature = nacl.sign.detached(message, voucherSigner.secretKey);
+      const redeem = () =>
+        program.methods
+          .buyWithVoucher(allocation, voucherData, Array.from(signature))
+          .accounts({
+            saleConfig: saleConfigPda,
+            buyerEscrow: buyerEscrowFor(saleConfigPda, replayBuyer.publicKey),
+            voucherNonce: voucherNonceFor(voucherData),
+            saleVault: saleVaultPda,
+            buyer: replayBuyer.publicKey,
+            treasury: treasury.publicKey,
+            paymentEscrow: paymentEscrowFor(saleConfigPda),
+            voucherSigner: voucherSigner.publicKey,
+            instructions: SYSVAR_INSTRUCTIONS_PUBKEY,
+            systemProgram: SystemProgram.programId,
+          })
+          .preInstructions([ed25519Ix(voucherSigner, message, signature)])
+          .signers([replayBuyer])
+          .rpc();
+
+      await redeem();
+      try {
+        await redeem();
+        assert.fail("Expected replayed voucher to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("VoucherAlreadyUsed");
+      }
+
+      const escrow = await program.account.buyerEscrow.fetch(
+        buyerEscrowFor(saleConfigPda, replayBuyer.publicKey)
+      );
+      assert.equal(escrow.allocation.toString(), allocation.toString());
+    });
+  });
+
+  describe("per-buyer caps", () => {
+    it("Applies the voucher cap to the buyer's running total", async () => {
+      const capped = await fundedKeypair();
+      const cap = new anchor.BN(1_000);
+
+      await buyWithVoucher(
+        saleConfigPda,
+        treasury.publicKey,
+        capped,
+        new anchor.BN(600),
+        new anchor.BN(1),
+        cap
+      );
+
+      try {
+        await buyWithVoucher(
+          saleConfigPda,
+          treasury.publicKey,
+          capped,
+          new anchor.BN(500),
+          new anchor.BN(2),
+          cap
+        );
+        assert.fail("Expected purchase past the voucher cap to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("ExceedsAllocation");
+      }
+
+      // Topping up to exactly the cap is still allowed
+      await buyWithVoucher(
+        saleConfigPda,
+        treasury.publicKey,
+        capped,
+        new anchor.BN(400),
+        new anchor.BN(3),
+        cap
+      );
+      const escrow = await program.account.buyerEscrow.fetch(
+        buyerEscrowFor(saleConfigPda, capped.publicKey)
+      );
+      assert.equal(escrow.allocation.toString(), cap.toString());
+    });
+
+    it("Enforces max_per_wallet over a generous voucher", async () => {
+      const maxPerWallet = new anchor.BN(1_000);
+      const { sale } = await initSale(await fundedKeypair(), { maxPerWallet });
+
+      const walletBuyer = await fundedKeypair();
+      const voucherCap = new anchor.BN(5_000);
+      await buyWithVoucher(
+        sale,
+        treasury.publicKey,
+        walletBuyer,
+        new anchor.BN(800),
+        new anchor.BN(1),
+        voucherCap
+      );
+
+      try {
+        await buyWithVoucher(
+          sale,
+          treasury.publicKey,
+          walletBuyer,
+          new anchor.BN(300),
+          new anchor.BN(2),
+          voucherCap
+        );
+        assert.fail("Expected purchase past max_per_wallet to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("ExceedsWalletCap");
+      }
+
+      const saleConfig = await program.account.saleConfig.fetch(sale);
+      assert.equal(saleConfig.maxPerWallet.toString(), maxPerWallet.toString());
+      assert.equal(saleConfig.sold.toString(), "800");
+    });
+  });
+
+  describe("sale updates", () => {
+    const emptyUpdate = {
+      priceLamportsPerToken: null,
+      startTs: null,
+      endTs: null,
+      totalAllocated: null,
+      treasury: null,
+    };
+
+    // Creates an unfunded sale owned by a fresh keypair
+    const createSale = async (
+      saleStartTs: anchor.BN,
+      overrides: Record<string, any> = {}
+    ) =>
+      initSale(
+        await fundedKeypair(),
+        { startTs: saleStartTs, tgeTs: saleStartTs, ...overrides },
+        { fund: false }
+      );
+
+    const updateSale = (
+      owner: Keypair,
+      sale: PublicKey,
+      update: Record<string, any>
+    ) =>
+      program.methods
+        .updateSale({ ...emptyUpdate, ...update })
+        .accounts({ saleConfig: sale, owner: owner.publicKey })
+        .signers([owner])
+        .rpc({ commitment: "confirmed" });
+
+    it("Owner updates price and timing before the sale starts", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      const { owner, sale } = await createSale(new anchor.BN(now + 3600));
+      const newTreasury = Keypair.generate().publicKey;
+      const newPrice = new anchor.BN(2_000_000);
+      const newEndTs = endTs.add(new anchor.BN(86400));
+
+      const tx = await updateSale(owner, sale, {
+        priceLamportsPerToken: newPrice,
+        startTs: new anchor.BN(now + 7200),
+        endTs: newEndTs,
+        totalAllocated: new anchor.BN(500_000),
+        treasury: newTreasury,
+      });
+
+      const saleConfig = await program.account.saleConfig.fetch(sale);
+      assert.equal(saleConfig.priceLamportsPerToken.toString(), "2000000");
+      assert.equal(saleConfig.startTs.toString(), (now + 7200).toString());
+      assert.equal(saleConfig.endTs.toString(), newEndTs.toString());
+      assert.equal(saleConfig.totalAllocated.toString(), "500000");
+      assert.ok(saleConfig.treasury.equals(newTreasury));
+
+      const txInfo = await provider.connection.getTransaction(tx, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const parser = new anchor.EventParser(
+        program.programId,
+        new anchor.BorshCoder(program.idl)
+      );
+      const events = [...parser.parseLogs(txInfo.meta.logMessages)];
+      const updated = events.find((event) => event.name === "saleUpdated");
+      assert.ok(updated, "Expected SaleUpdated event");
+      assert.equal(
+        updated.data.oldPriceLamportsPerToken.toString(),
+        pricePerToken.toString()
+      );
+      assert.equal(updated.data.newPriceLamportsPerToken.toString(), "2000000");
+      assert.ok(updated.data.oldTreasury.equals(treasury.publicKey));
+      assert.ok(updated.data.newTreasury.equals(newTreasury));
+    });
+
+    it("Rejects a price change after the sale starts", async () => {
+      const { owner, sale } = await createSale(startTs);
+
+      try {
+        await updateSale(owner, sale, {
+          priceLamportsPerToken: new anchor.BN(1),
+        });
+        assert.fail("Expected price change after start to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("SaleAlreadyStarted");
+      }
+    });
+
+    it("Only lets the end time be extended", async () => {
+      const { owner, sale } = await createSale(startTs);
+
+      try {
+        await updateSale(owner, sale, { endTs: endTs.subn(60) });
+        assert.fail("Expected shortening the sale to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidTimeRange");
+      }
+
+      await updateSale(owner, sale, { endTs: endTs.addn(3600) });
+      const saleConfig = await program.account.saleConfig.fetch(sale);
+      assert.equal(saleConfig.endTs.toString(), endTs.addn(3600).toString());
+    });
+
+    it("Rejects dropping total_allocated below sold", async () => {
+      const { owner, sale } = await createSale(startTs);
+      await fundSale(owner, sale, totalAllocated);
+      await buyWithVoucher(
+        sale,
+        treasury.publicKey,
+        await fundedKeypair(),
+        new anchor.BN(1_000),
+        new anchor.BN(1)
+      );
+
+      try {
+        await updateSale(owner, sale, { totalAllocated: new anchor.BN(999) });
+        assert.fail("Expected allocation below sold to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidAllocation");
+      }
+    });
+
+    it("Fixes the end time and allocation once the sale has ended", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      const { owner, sale } = await createSale(new anchor.BN(now - 60), {
+        endTs: new anchor.BN(now + 2),
+      });
+      await sleep(4);
+
+      try {
+        await updateSale(owner, sale, { endTs: new anchor.BN(now + 3600) });
+        assert.fail("Expected reopening an ended sale to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("SaleEnded");
+      }
+
+      try {
+        await updateSale(owner, sale, {
+          totalAllocated: totalAllocated.addn(1),
+        });
+        assert.fail("Expected raising an ended sale's allocation to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("SaleEnded");
+      }
+
+      // Other fields can still be updated
+      const newTreasury = Keypair.generate().publicKey;
+      await updateSale(owner, sale, { treasury: newTreasury });
+      const saleConfig = await program.account.saleConfig.fetch(sale);
+      assert.equal(saleConfig.treasury.toBase58(), newTreasury.toBase58());
+    });
+
+    it("Bounds how long an escrowed sale can be extended", async () => {
+      const { owner, sale } = await createSale(startTs, {
+        softCap: new anchor.BN(LAMPORTS_PER_SOL),
+      });
+      const maxDuration = 90 * 86400;
+
+      try {
+        await updateSale(owner, sale, { endTs: startTs.addn(maxDuration + 1) });
+        assert.fail("Expected extending past the escrow limit to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidTimeRange");
+      }
+
+      await updateSale(owner, sale, { endTs: startTs.addn(maxDuration) });
+      const saleConfig = await program.account.saleConfig.fetch(sale);
+      assert.equal(
+        saleConfig.endTs.toString(),
+        startTs.addn(maxDuration).toString()
+      );
+    });
+
+    it("Rejects updates from a non-owner", async () => {
+      const { sale } = await createSale(startTs);
+      const attacker = await fundedKeypair();
+
+      try {
+        await updateSale(attacker, sale, { endTs: endTs.addn(3600) });
+        assert.fail("Expected non-owner update to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("ConstraintHasOne");
+      }
+    });
+  });
+
+  describe("emergency pause", () => {
+    let owner: Keypair;
+    let sale: PublicKey;
+    let pauseBuyer: Keypair;
+
+    const setPaused = (
+      authority: Keypair,
+      paused: boolean,
+      pauseClaims = false
+    ) =>
+      (paused
+        ? program.methods.pauseSale(pauseClaims)
+        : program.methods.resumeSale()
+      )
+        .accounts({ saleConfig: sale, authority: authority.publicKey })
+        .signers([authority])
+        .rpc();
+
+    const claim = async () => {
+      const buyerAta = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        pauseBuyer,
+        tokenMint,
+        pauseBuyer.publicKey
+      );
+
+      return program.methods
+        .claimPurchased()
+        .accounts({
+          saleConfig: sale,
+          buyerEscrow: buyerEscrowFor(sale, pauseBuyer.publicKey),
+          saleVault: saleVaultFor(sale),
+          buyerTokenAccount: buyerAta.address,
+          tokenMint,
+          buyer: pauseBuyer.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        })
+        .signers([pauseBuyer])
+        .rpc();
+    };
+
+    before(async () => {
+      owner = await fundedKeypair();
+      pauseBuyer = await fundedKeypair();
+      ({ sale } = await initSale(owner));
+    });
+
+    it("Guardian pauses purchases mid-sale and the owner resumes", async () => {
+      await buyWithVoucher(
+        sale,
+        treasury.publicKey,
+        pauseBuyer,
+        new anchor.BN(1_000),
+        new anchor.BN(1),
+        new anchor.BN(5_000)
+      );
+
+      await setPaused(guardian, true);
+      const paused = await program.account.saleConfig.fetch(sale);
+      assert.isTrue(paused.paused);
+      assert.isFalse(paused.claimsPaused);
+
+      try {
+        await buyWithVoucher(
+          sale,
+          treasury.publicKey,
+          pauseBuyer,
+          new anchor.BN(1_000),
+          new anchor.BN(2),
+          new anchor.BN(5_000)
+        );
+        assert.fail("Expected purchase during pause to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("SalePaused");
+      }
+
+      await setPaused(owner, false);
+      await buyWithVoucher(
+        sale,
+        treasury.publicKey,
+        pauseBuyer,
+        new anchor.BN(1_000),
+        new anchor.BN(3),
+        new anchor.BN(5_000)
+      );
+
+      const saleConfig = await program.account.saleConfig.fetch(sale);
+      assert.isFalse(saleConfig.paused);
+      assert.equal(saleConfig.sold.toString(), "2000");
+    });
+
+    it("Pausing claims blocks claim_purchased until resumed", async () => {
+      await setPaused(owner, true, true);
+      // A later purchase-only pause leaves claims paused
+      await setPaused(guardian, true);
+      const paused = await program.account.saleConfig.fetch(sale);
+      assert.isTrue(paused.claimsPaused);
+
+      try {
+        await claim();
+        assert.fail("Expected claim during pause to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("ClaimsPaused");
+      }
+
+      await setPaused(guardian, false);
+      await claim();
+
+      const escrow = await program.account.buyerEscrow.fetch(
+        buyerEscrowFor(sale, pauseBuyer.publicKey)
+      );
+      assert.equal(escrow.claimed.toString(), escrow.allocation.toString());
+    });
+
+    it("Rejects pausing by anyone but the owner or guardian", async () => {
+      const stranger = await fundedKeypair();
+
+      try {
+        await setPaused(stranger, true);
+        assert.fail("Expected pause by a stranger to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("Unauthorized");
+      }
+    });
+  });
+
+  describe("ownership transfer", () => {
+    let creator: Keypair;
+    let newOwner: Keypair;
+    let sale: PublicKey;
+
+    const createVesting = (owner: Keypair, ownerTokenAccount: PublicKey) => {
+      const beneficiary = Keypair.generate().publicKey;
+      const vesting = vestingFor(sale, beneficiary);
+
+      return program.methods
+        .createVesting(
+          beneficiary,
+          new anchor.BN(0),
+          new anchor.BN(5_000),
+          new anchor.BN(Math.floor(Date.now() / 1000)),
+          new anchor.BN(0),
+          new anchor.BN(86400),
+          true
+        )
+        .accounts({
+          saleConfig: sale,
+          vesting,
+          vestingVault: vestingVaultFor(vesting),
+          ownerTokenAccount,
+          tokenMint,
+          owner: owner.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([owner])
+        .rpc()
+        .then(() => vesting);
+    };
+
+    before(async () => {
+      creator = await fundedKeypair();
+      newOwner = await fundedKeypair();
+      ({ sale } = await initSale(creator, {}, { fund: false }));
+    });
+
+    it("Only the proposed owner can accept", async () => {
+      await program.methods
+        .proposeOwner(newOwner.publicKey)
+        .accounts({ saleConfig: sale, owner: creator.publicKey })
+        .signers([creator])
+        .rpc();
+
+      const stranger = await fundedKeypair();
+      try {
+        await program.methods
+          .acceptOwner()
+          .accounts({ saleConfig: sale, newOwner: stranger.publicKey })
+          .signers([stranger])
+          .rpc();
+        assert.fail("Expected accept by a stranger to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("Unauthorized");
+      }
+
+      await program.methods
+        .acceptOwner()
+        .accounts({ saleConfig: sale, newOwner: newOwner.publicKey })
+        .signers([newOwner])
+        .rpc();
+
+      const saleConfig = await program.account.saleConfig.fetch(sale);
+      assert.ok(saleConfig.owner.equals(newOwner.publicKey));
+      assert.ok(saleConfig.creator.equals(creator.publicKey));
+      assert.isNull(saleConfig.pendingOwner);
+    });
+
+    it("New owner creates and revokes vesting at the same sale address", async () => {
+      const ownerTokenAccount = await mintToOwner(
+        newOwner,
+        new anchor.BN(5_000)
+      );
+      const vesting = await createVesting(newOwner, ownerTokenAccount);
+
+      await program.methods
+        .revokeVesting()
+        .accounts({
+          saleConfig: sale,
+          vesting,
+          vestingVault: vestingVaultFor(vesting),
+          treasuryTokenAccount: await treasuryTokenAccountFor(newOwner),
+          tokenMint,
+          owner: newOwner.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([newOwner])
+        .rpc();
+
+      const revoked = await program.account.vesting.fetch(vesting);
+      assert.isTrue(revoked.revoked);
+      assert.ok(revoked.sale.equals(sale));
+    });
+
+    it("Previous owner loses admin rights", async () => {
+      const ownerTokenAccount = await mintToOwner(
+        creator,
+        new anchor.BN(5_000)
+      );
+
+      try {
+        await createVesting(creator, ownerTokenAccount);
+        assert.fail("Expected vesting by the previous owner to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("ConstraintHasOne");
+      }
+    });
+  });
+
+  describe("soft cap refunds", () => {
+    const saleLength = 6;
+
+    const softCapSale = async (softCap: anchor.BN) => {
+      const now = Math.floor(Date.now() / 1000);
+      const saleEndTs = new anchor.BN(now + saleLength);
+      const { owner, sale } = await initSale(await fundedKeypair(), {
+        startTs: new anchor.BN(now - 60),
+        endTs: saleEndTs,
+        tgeTs: new anchor.BN(now - 60),
+        softCap,
+      });
+
+      return { owner, sale, saleEndTs };
+    };
+
+    const refund = (sale: PublicKey, refundBuyer: Keypair) =>
+      program.methods
+        .refund()
+        .accounts({
+          saleConfig: sale,
+          buyerEscrow: buyerEscrowFor(sale, refundBuyer.publicKey),
+          paymentEscrow: paymentEscrowFor(sale),
+          buyer: refundBuyer.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([refundBuyer])
+        .rpc();
+
+    const claim = async (sale: PublicKey, claimer: Keypair) => {
+      const claimerAta = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        claimer,
+        tokenMint,
+        claimer.publicKey
+      );
+
+      return program.methods
+        .claimPurchased()
+        .accounts({
+          saleConfig: sale,
+          buyerEscrow: buyerEscrowFor(sale, claimer.publicKey),
+          saleVault: saleVaultFor(sale),
+          buyerTokenAccount: claimerAta.address,
+          tokenMint,
+          buyer: claimer.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        })
+        .signers([claimer])
+        .rpc();
+    };
+
+    it("Escrows payments and refunds buyers when the soft cap is missed", async () => {
+      const { sale, saleEndTs } = await softCapSale(
+        new anchor.BN(100 * LAMPORTS_PER_SOL)
+      );
+      const refundBuyer = await fundedKeypair();
+      const allocation = new anchor.BN(1_000);
+      const payment = paymentFor(allocation).toNumber();
+      const escrowBefore = await provider.connection.getBalance(
+        paymentEscrowFor(sale)
+      );
+      const treasuryBefore = await provider.connection.getBalance(
+        treasury.publicKey
+      );
+
+      await buyWithVoucher(
+        sale,
+        treasury.publicKey,
+        refundBuyer,
+        allocation,
+        new anchor.BN(1)
+      );
+
+      assert.equal(
+        await provider.connection.getBalance(paymentEscrowFor(sale)),
+        escrowBefore + payment
+      );
+      assert.equal(
+        await provider.connection.getBalance(treasury.publicKey),
+        treasuryBefore
+      );
+
+      try {
+        await finalize(sale);
+        assert.fail("Expected finalize before the end to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("SaleNotEnded");
+      }
+      try {
+        await claim(sale, refundBuyer);
+        assert.fail("Expected claim before finalize to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("SaleNotFinalized");
+      }
+
+      await sleep(saleEndTs.toNumber() + 2 - Date.now() / 1000);
+      await finalize(sale);
+      const finalized = await program.account.saleConfig.fetch(sale);
+      assert.isTrue(finalized.finalized);
+      assert.isTrue(finalized.refunding);
+
+      try {
+        await claim(sale, refundBuyer);
+        assert.fail("Expected claim after a missed soft cap to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("SoftCapNotMet");
+      }
+
+      const buyerBefore = await provider.connection.getBalance(
+        refundBuyer.publicKey
+      );
+      await refund(sale, refundBuyer);
+
+      assert.isAbove(
+        await provider.connection.getBalance(refundBuyer.publicKey),
+        buyerBefore + payment - 10_000
+      );
+      assert.equal(
+        await provider.connection.getBalance(paymentEscrowFor(sale)),
+        escrowBefore
+      );
+      assert.isNull(
+        await program.account.buyerEscrow.fetchNullable(
+          buyerEscrowFor(sale, refundBuyer.publicKey)
+        )
+      );
+      const saleConfig = await program.account.saleConfig.fetch(sale);
+      assert.equal(saleConfig.sold.toString(), "0");
+      assert.equal(saleConfig.raised.toString(), "0");
+    });
+
+    it("Releases escrow to the treasury when the soft cap is met", async () => {
+      const allocation = new anchor.BN(1_000);
+      const payment = paymentFor(allocation);
+      const { sale, saleEndTs } = await softCapSale(payment);
+      const capBuyer = await fundedKeypair();
+
+      await buyWithVoucher(
+        sale,
+        treasury.publicKey,
+        capBuyer,
+        allocation,
+        new anchor.BN(1)
+      );
+
+      await sleep(saleEndTs.toNumber() + 2 - Date.now() / 1000);
+      const treasuryBefore = await provider.connection.getBalance(
+        treasury.publicKey
+      );
+      await finalize(sale);
+
+      assert.equal(
+        await provider.connection.getBalance(treasury.publicKey),
+        treasuryBefore + payment.toNumber()
+      );
+
+      try {
+        await refund(sale, capBuyer);
+        assert.fail("Expected refund after a met soft cap to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("RefundsNotEnabled");
+      }
+
+      await claim(sale, capBuyer);
+      const escrow = await program.account.buyerEscrow.fetch(
+        buyerEscrowFor(sale, capBuyer.publicKey)
+      );
+      assert.equal(escrow.claimed.toString(), allocation.toString());
+    });
+  });
+
+  describe("hard cap", () => {
+    it("Rejects purchases past the payment cap and completes at the cap", async () => {
+      const hardCapPayment = paymentFor(new anchor.BN(1_500));
+      // A soft cap escrows payments so the completed sale can be finalized
+      const { sale } = await initSale(await fundedKeypair(), {
+        hardCapPayment,
+        softCap: paymentFor(new anchor.BN(1_000)),
+      });
+      const first = await fundedKeypair();
+      const second = await fundedKeypair();
+
+      await buyWithVoucher(
+        sale,
+        treasury.publicKey,
+        first,
+        new anchor.BN(1_000),
+        new anchor.BN(1)
+      );
+
+      try {
+        await buyWithVoucher(
+          sale,
+          treasury.publicKey,
+          second,
+          new anchor.BN(1_000),
+          new anchor.BN(1)
+        );
+        assert.fail("Expected purchase past the hard cap to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("HardCapExceeded");
+      }
+
+      await buyWithVoucher(
+        sale,
+        treasury.publicKey,
+        second,
+        new anchor.BN(500),
+        new anchor.BN(2)
+      );
+      const saleConfig = await program.account.saleConfig.fetch(sale);
+      assert.equal(saleConfig.raised.toString(), hardCapPayment.toString());
+      assert.isTrue(saleConfig.completed);
+
+      try {
+        await buyWithVoucher(
+          sale,
+          treasury.publicKey,
+          await fundedKeypair(),
+          new anchor.BN(1),
+          new anchor.BN(1)
+        );
+        assert.fail("Expected purchase after completion to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("SaleCompleted");
+      }
+
+      // Completed sales settle without waiting for end_ts
+      await finalize(sale);
+      const finalized = await program.account.saleConfig.fetch(sale);
+      assert.isTrue(finalized.finalized);
+    });
+
+    it("Completes the sale when the token allocation sells out", async () => {
+      const { sale } = await initSale(await fundedKeypair(), {
+        totalAllocated: new anchor.BN(1_000),
+      });
+
+      await buyWithVoucher(
+        sale,
+        treasury.publicKey,
+        await fundedKeypair(),
+        new anchor.BN(1_000),
+        new anchor.BN(1)
+      );
+
+      const saleConfig = await program.account.saleConfig.fetch(sale);
+      assert.equal(saleConfig.sold.toString(), "1000");
+      assert.isTrue(saleConfig.completed);
+
+      // Payments went straight to the treasury, so there is nothing to settle
+      try {
+        await finalize(sale);
+        assert.fail("Expected finalizing a non-escrow sale to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("PaymentsNotEscrowed");
+      }
+    });
+  });
+
+  describe("price tiers", () => {
+    const tieredSale = async (
+      tierMode: Record<string, object>,
+      priceTiers: { limit: anchor.BN; priceLamportsPerToken: anchor.BN }[]
+    ) =>
+      (await initSale(await fundedKeypair(), { tierMode, priceTiers })).sale;
+
+    const tierA = new anchor.BN(100_000_000); // 0.1 SOL per whole token
+    const tierB = new anchor.BN(200_000_000); // 0.2 SOL per whole token
+    const bn = (value: number) => new anchor.BN(value);
+
+    it("Charges each sold tier up to its boundary exactly", async () => {
+      const sale = await tieredSale({ sold: {} }, [
+        { limit: new anchor.BN(1_000), priceLamportsPerToken: tierA },
+        { limit: new anchor.BN(3_000), priceLamportsPerToken: tierB },
+      ]);
+
+      // Ends exactly on the first boundary
+      assert.equal((await buyAsNewBuyer(sale, 1_000)).paid, 100);
+      // Starts exactly on the boundary, stays in the second tier;
+      // 399.8 lamports rounds up
+      assert.equal((await buyAsNewBuyer(sale, 1_999)).paid, 400);
+      // Crosses from the second tier into the base price
+      assert.equal(
+        (await buyAsNewBuyer(sale, 11)).paid,
+        toPayment(tierB.add(bn(10).mul(pricePerToken))).toNumber()
+      );
+    });
+
+    it("Splits a purchase that spans several tiers", async () => {
+      const sale = await tieredSale({ sold: {} }, [
+        { limit: new anchor.BN(1_000), priceLamportsPerToken: tierA },
+        { limit: new anchor.BN(3_000), priceLamportsPerToken: tierB },
+      ]);
+
+      assert.equal(
+        (await buyAsNewBuyer(sale, 999)).paid,
+        paymentFor(bn(999), tierA).toNumber()
+      );
+      // Split pricing is summed before rounding, not rounded per tier
+      assert.equal(
+        (await buyAsNewBuyer(sale, 2_002)).paid,
+        toPayment(
+          tierA.add(bn(2_000).mul(tierB)).add(pricePerToken)
+        ).toNumber()
+      );
+    });
+
+    it("Prices by the current time window", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      const sale = await tieredSale({ time: {} }, [
+        { limit: new anchor.BN(now - 60), priceLamportsPerToken: tierA },
+        { limit: new anchor.BN(now + 3600), priceLamportsPerToken: tierB },
+      ]);
+
+      // The first window has closed, so the second one applies
+      assert.equal(
+        (await buyAsNewBuyer(sale, 500)).paid,
+        paymentFor(bn(500), tierB).toNumber()
+      );
+    });
+
+    it("Rejects tiers without increasing limits", async () => {
+      try {
+        await tieredSale({ sold: {} }, [
+          { limit: new anchor.BN(3_000), priceLamportsPerToken: tierA },
+          { limit: new anchor.BN(3_000), priceLamportsPerToken: tierB },
+        ]);
+        assert.fail("Expected unordered tiers to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidPriceTiers");
+      }
+    });
+  });
+
+  describe("dutch auction", () => {
+    const startPrice = new anchor.BN(2 * LAMPORTS_PER_SOL);
+    const floorPrice = new anchor.BN(LAMPORTS_PER_SOL);
+    const midPrice = new anchor.BN(1.5 * LAMPORTS_PER_SOL);
+
+    const auctionSale = async (
+      saleStartTs: number,
+      saleEndTs: number,
+      stepSeconds: number,
+      clearingPrice = false
+    ) =>
+      initSale(await fundedKeypair(), {
+        startTs: new anchor.BN(saleStartTs),
+        endTs: new anchor.BN(saleEndTs),
+        tgeTs: new anchor.BN(saleStartTs),
+        auction: {
+          startPrice,
+          floorPrice,
+          stepSeconds: new anchor.BN(stepSeconds),
+          clearingPrice,
+        },
+      });
+
+    it("Decays the price linearly toward the floor", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      // Halfway through a one hour auction
+      const { sale } = await auctionSale(now - 1800, now + 1800, 0);
+
+      const { paid } = await buyAsNewBuyer(sale, 100_000);
+      const saleConfig = await program.account.saleConfig.fetch(sale);
+      assert.approximately(
+        saleConfig.lastPrice.toNumber(),
+        midPrice.toNumber(),
+        midPrice.toNumber() / 100
+      );
+      assert.equal(
+        paid,
+        paymentFor(new anchor.BN(100_000), saleConfig.lastPrice).toNumber()
+      );
+    });
+
+    it("Holds the price between steps", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      // Half an hour into an auction that steps every hour
+      const { sale } = await auctionSale(now - 1800, now + 5400, 3600);
+
+      const { paid } = await buyAsNewBuyer(sale, 100_000);
+      assert.equal(
+        paid,
+        paymentFor(new anchor.BN(100_000), startPrice).toNumber()
+      );
+    });
+
+    it("Rebates early buyers down to the clearing price", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      // Two 6 second steps: start price, then halfway to the floor
+      const { sale } = await auctionSale(now - 1, now + 11, 6, true);
+
+      const allocation = new anchor.BN(1_000);
+      const early = await buyAsNewBuyer(sale, 1_000);
+      assert.equal(early.paid, paymentFor(allocation, startPrice).toNumber());
+      await sleep(6);
+      const late = await buyAsNewBuyer(sale, 1_000);
+      assert.equal(late.paid, paymentFor(allocation, midPrice).toNumber());
+
+      await sleep(now + 13 - Date.now() / 1000);
+      const treasuryBefore = await provider.connection.getBalance(
+        treasury.publicKey
+      );
+      await finalize(sale);
+      assert.equal(
+        await provider.connection.getBalance(treasury.publicKey),
+        treasuryBefore + paymentFor(allocation.muln(2), midPrice).toNumber()
+      );
+
+      const rebate = (rebateBuyer: Keypair) =>
+        program.methods
+          .claimRebate()
+          .accounts({
+            saleConfig: sale,
+            buyerEscrow: buyerEscrowFor(sale, rebateBuyer.publicKey),
+            paymentEscrow: paymentEscrowFor(sale),
+            buyer: rebateBuyer.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([rebateBuyer])
+          .rpc();
+
+      await rebate(early.buyer);
+      const escrow = await program.account.buyerEscrow.fetch(
+        buyerEscrowFor(sale, early.buyer.publicKey)
+      );
+      assert.equal(
+        escrow.paid.toNumber(),
+        paymentFor(allocation, midPrice).toNumber()
+      );
+
+      try {
+        await rebate(late.buyer);
+        assert.fail("Expected rebate at the clearing price to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("NothingToClaim");
+      }
+    });
+
+    it("Keeps a started clearing auction's end time fixed", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      const { owner, sale } = await auctionSale(now - 1, now + 6, 0, true);
+      const allocation = new anchor.BN(1_000);
+      const { paid } = await buyAsNewBuyer(sale, 1_000);
+
+      // A later end would push the price back up and strand the escrow
+      try {
+        await program.methods
+          .updateSale({
+            priceLamportsPerToken: null,
+            startTs: null,
+            endTs: new anchor.BN(now + 86400),
+            totalAllocated: null,
+            treasury: null,
+          })
+          .accounts({ saleConfig: sale, owner: owner.publicKey })
+          .signers([owner])
+          .rpc();
+        assert.fail("Expected extending a started auction to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("SaleAlreadyStarted");
+      }
+
+      await sleep(now + 8 - Date.now() / 1000);
+      const treasuryBefore = await provider.connection.getBalance(
+        treasury.publicKey
+      );
+      await finalize(sale);
+
+      const saleConfig = await program.account.saleConfig.fetch(sale);
+      assert.isTrue(saleConfig.finalized);
+      assert.equal(
+        await provider.connection.getBalance(treasury.publicKey),
+        treasuryBefore + paymentFor(allocation, saleConfig.lastPrice).toNumber()
+      );
+      assert.equal(
+        paid,
+        paymentFor(allocation, saleConfig.lastPrice).toNumber()
+      );
+    });
+  });
+
+  describe("events", () => {
+    it("Emits SaleInitialized and TokensPurchased", async () => {
+      const { owner, sale, initTx } = await initSale(await fundedKeypair());
+
+      const initialized = await eventNamed(initTx, "saleInitialized");
+      assert.ok(initialized.sale.equals(sale));
+      assert.ok(initialized.owner.equals(owner.publicKey));
+      assert.ok(initialized.tokenMint.equals(tokenMint));
+      assert.ok(initialized.treasury.equals(treasury.publicKey));
+      assert.isNull(initialized.paymentMint);
+      assert.equal(
+        initialized.totalAllocated.toString(),
+        totalAllocated.toString()
+      );
+      assert.equal(initialized.tokenDecimals, tokenDecimals);
+      assert.ok(initialized.guardian.equals(guardian.publicKey));
+      assert.isNull(initialized.maxPerWallet);
+      assert.isNull(initialized.softCap);
+      assert.isNull(initialized.hardCapPayment);
+      assert.deepEqual(initialized.tierMode, { sold: {} });
+      assert.deepEqual(initialized.priceTiers, []);
+      assert.isNull(initialized.auction);
+
+      const eventBuyer = await fundedKeypair();
+      const allocation = new anchor.BN(1_000);
+      await buyWithVoucher(
+        sale,
+        treasury.publicKey,
+        eventBuyer,
+        allocation,
+        new anchor.BN(1),
+        allocation.muln(2)
+      );
+      const buyTx = await buyWithVoucher(
+        sale,
+        treasury.publicKey,
+        eventBuyer,
+        allocation,
+        new anchor.BN(2),
+        allocation.muln(2)
+      );
+
+      const purchased = await eventNamed(buyTx, "tokensPurchased");
+      const payment = paymentFor(allocation);
+      assert.ok(purchased.sale.equals(sale));
+      assert.ok(purchased.buyer.equals(eventBuyer.publicKey));
+      assert.isNull(purchased.paymentMint);
+      assert.equal(purchased.allocation.toString(), allocation.toString());
+      assert.equal(purchased.payment.toString(), payment.toString());
+      assert.equal(purchased.nonce.toString(), "2");
+      assert.isNull(purchased.tier);
+      // Totals are reported after the purchase
+      assert.equal(purchased.buyerAllocation.toString(), "2000");
+      assert.equal(
+        purchased.buyerPaid.toString(),
+        payment.muln(2).toString()
+      );
+      assert.equal(purchased.sold.toString(), "2000");
+      assert.equal(purchased.raised.toString(), payment.muln(2).toString());
+    });
+
+    it("Emits SaleFunded and PurchaseClaimed", async () => {
+      const { sale, fundTx } = await initSale(await fundedKeypair());
+      const funded = await eventNamed(fundTx, "saleFunded");
+      assert.ok(funded.sale.equals(sale));
+      assert.equal(funded.amount.toString(), totalAllocated.toString());
+      assert.equal(funded.vaultBalance.toString(), totalAllocated.toString());
+
+      const eventBuyer = await fundedKeypair();
+      await buyWithVoucher(
+        sale,
+        treasury.publicKey,
+        eventBuyer,
+        new anchor.BN(1_000),
+        new anchor.BN(1)
+      );
+      const buyerAta = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        eventBuyer,
+        tokenMint,
+        eventBuyer.publicKey
+      );
+      const claimTx = await program.methods
+        .claimPurchased()
+        .accounts({
+          saleConfig: sale,
+          buyerEscrow: buyerEscrowFor(sale, eventBuyer.publicKey),
+          saleVault: saleVaultFor(sale),
+          buyerTokenAccount: buyerAta.address,
+          tokenMint,
+          buyer: eventBuyer.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          associatedTokenProgram: ASSOCIATED_TOKEN_PROGRAM_ID,
+        })
+        .signers([eventBuyer])
+        .rpc();
+
+      const claimed = await eventNamed(claimTx, "purchaseClaimed");
+      assert.ok(claimed.buyer.equals(eventBuyer.publicKey));
+      assert.equal(claimed.amount.toString(), "1000");
+      assert.equal(claimed.buyerClaimed.toString(), "1000");
+      assert.equal(claimed.buyerAllocation.toString(), "1000");
+      assert.equal(claimed.claimed.toString(), "1000");
+    });
+
+    it("Emits SaleFinalized, Refunded and UnsoldWithdrawn", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      const { owner, sale } = await initSale(await fundedKeypair(), {
+        startTs: new anchor.BN(now - 60),
+        endTs: new anchor.BN(now + 6),
+        tgeTs: new anchor.BN(now - 60),
+        softCap: new anchor.BN(100 * LAMPORTS_PER_SOL),
+      });
+      const eventBuyer = await fundedKeypair();
+      const allocation = new anchor.BN(1_000);
+      await buyWithVoucher(
+        sale,
+        treasury.publicKey,
+        eventBuyer,
+        allocation,
+        new anchor.BN(1)
+      );
+
+      await sleep(now + 8 - Date.now() / 1000);
+      const finalized = await eventNamed(
+        await finalize(sale),
+        "saleFinalized"
+      );
+      assert.isTrue(finalized.refunding);
+      assert.equal(finalized.proceeds.toString(), "0");
+      assert.isNull(finalized.clearingPrice);
+
+      const refundTx = await program.methods
+        .refund()
+        .accounts({
+          saleConfig: sale,
+          buyerEscrow: buyerEscrowFor(sale, eventBuyer.publicKey),
+          paymentEscrow: paymentEscrowFor(sale),
+          buyer: eventBuyer.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([eventBuyer])
+        .rpc();
+      const refunded = await eventNamed(refundTx, "refunded");
+      assert.ok(refunded.buyer.equals(eventBuyer.publicKey));
+      assert.equal(
+        refunded.amount.toString(),
+        paymentFor(allocation).toString()
+      );
+      assert.equal(refunded.sold.toString(), "0");
+      assert.equal(refunded.raised.toString(), "0");
+
+      const ownerAta = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        owner,
+        tokenMint,
+        owner.publicKey
+      );
+      const withdrawTx = await program.methods
+        .withdrawUnsold()
+        .accounts({
+          saleConfig: sale,
+          saleVault: saleVaultFor(sale),
+          ownerTokenAccount: ownerAta.address,
+          tokenMint,
+          owner: owner.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([owner])
+        .rpc();
+      const withdrawn = await eventNamed(withdrawTx, "unsoldWithdrawn");
+      assert.ok(withdrawn.destination.equals(ownerAta.address));
+      assert.equal(withdrawn.amount.toString(), totalAllocated.toString());
+      assert.equal(withdrawn.outstanding.toString(), "0");
+    });
+
+    it("Emits RebateClaimed at the clearing price", async () => {
+      const now = Math.floor(Date.now() / 1000);
+      const startPrice = new anchor.BN(2 * LAMPORTS_PER_SOL);
+      const midPrice = new anchor.BN(1.5 * LAMPORTS_PER_SOL);
+      // Two 6 second steps: start price, then halfway to the floor
+      const { sale } = await initSale(await fundedKeypair(), {
+        startTs: new anchor.BN(now - 1),
+        endTs: new anchor.BN(now + 11),
+        tgeTs: new anchor.BN(now - 1),
+        auction: {
+          startPrice,
+          floorPrice: new anchor.BN(LAMPORTS_PER_SOL),
+          stepSeconds: new anchor.BN(6),
+          clearingPrice: true,
+        },
+      });
+      const allocation = new anchor.BN(1_000);
+      const early = await fundedKeypair();
+      await buyWithVoucher(
+        sale,
+        treasury.publicKey,
+        early,
+        allocation,
+        new anchor.BN(1)
+      );
+      await sleep(now + 6 - Date.now() / 1000);
+      await buyWithVoucher(
+        sale,
+        treasury.publicKey,
+        await fundedKeypair(),
+        allocation,
+        new anchor.BN(1)
+      );
+
+      await sleep(now + 13 - Date.now() / 1000);
+      const finalized = await eventNamed(
+        await finalize(sale),
+        "saleFinalized"
+      );
+      assert.equal(finalized.clearingPrice.toString(), midPrice.toString());
+      assert.equal(
+        finalized.proceeds.toString(),
+        paymentFor(allocation.muln(2), midPrice).toString()
+      );
+
+      const rebateTx = await program.methods
+        .claimRebate()
+        .accounts({
+          saleConfig: sale,
+          buyerEscrow: buyerEscrowFor(sale, early.publicKey),
+          paymentEscrow: paymentEscrowFor(sale),
+          buyer: early.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([early])
+        .rpc();
+      const rebated = await eventNamed(rebateTx, "rebateClaimed");
+      const owed = paymentFor(allocation, midPrice);
+      assert.ok(rebated.buyer.equals(early.publicKey));
+      assert.equal(rebated.clearingPrice.toString(), midPrice.toString());
+      assert.equal(rebated.buyerPaid.toString(), owed.toString());
+      assert.equal(
+        rebated.amount.toString(),
+        paymentFor(allocation, startPrice).sub(owed).toString()
+      );
+    });
+
+    it("Emits vesting created, claimed and revoked events", async () => {
+      const beneficiary = await fundedKeypair();
+      const amount = new anchor.BN(5_000);
+      const scheduleIndex = new anchor.BN(7);
+      const vesting = vestingFor(
+        saleConfigPda,
+        beneficiary.publicKey,
+        scheduleIndex
+      );
+      const ownerTokenAccount = await mintToOwner(saleOwner, amount);
+
+      const createTx = await program.methods
+        .createVesting(
+          beneficiary.publicKey,
+          scheduleIndex,
+          amount,
+          new anchor.BN(Math.floor(Date.now() / 1000) - 3600),
+          new anchor.BN(0),
+          new anchor.BN(86400),
+          true
+        )
+        .accounts({
+          saleConfig: saleConfigPda,
+          vesting,
+          vestingVault: vestingVaultFor(vesting),
+          ownerTokenAccount,
+          tokenMint,
+          owner: saleOwner.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([saleOwner])
+        .rpc();
+
+      const created = await eventNamed(createTx, "vestingCreated");
+      assert.ok(created.vesting.equals(vesting));
+      assert.ok(created.beneficiary.equals(beneficiary.publicKey));
+      assert.equal(created.scheduleIndex.toString(), "7");
+      assert.equal(created.totalAmount.toString(), amount.toString());
+      assert.isTrue(created.revocable);
+
+      const beneficiaryAta = await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        beneficiary,
+        tokenMint,
+        beneficiary.publicKey
+      );
+      const claimTx = await program.methods
+        .claimVested()
+        .accounts({
+          vesting,
+          vestingVault: vestingVaultFor(vesting),
+          beneficiaryTokenAccount: beneficiaryAta.address,
+          tokenMint,
+          beneficiary: beneficiary.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([beneficiary])
+        .rpc();
+
+      const claimed = await eventNamed(claimTx, "vestingClaimed");
+      assert.ok(claimed.amount.gtn(0));
+      assert.equal(claimed.released.toString(), claimed.amount.toString());
+      assert.equal(claimed.totalAmount.toString(), amount.toString());
+
+      const treasuryTokenAccount = await treasuryTokenAccountFor(saleOwner);
+      const revokeTx = await program.methods
+        .revokeVesting()
+        .accounts({
+          saleConfig: saleConfigPda,
+          vesting,
+          vestingVault: vestingVaultFor(vesting),
+          treasuryTokenAccount,
+          tokenMint,
+          owner: saleOwner.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([saleOwner])
+        .rpc();
+
+      const revoked = await eventNamed(revokeTx, "vestingRevoked");
+      assert.ok(revoked.destination.equals(treasuryTokenAccount));
+      assert.equal(
+        revoked.unvested.add(revoked.vested).toString(),
+        amount.toString()
+      );
+      assert.equal(revoked.released.toString(), claimed.released.toString());
+    });
+
+    it("Emits admin events for signer, allowlist, ownership and pause changes", async () => {
+      const { owner, sale } = await initSale(await fundedKeypair());
+      const newSigner = Keypair.generate().publicKey;
+      const rotated = await eventNamed(
+        await program.methods
+          .rotateVoucherSigner(newSigner)
+          .accounts({ saleConfig: sale, owner: owner.publicKey })
+          .signers([owner])
+          .rpc(),
+        "voucherSignerRotated"
+      );
+      assert.ok(rotated.oldVoucherSigner.equals(voucherSigner.publicKey));
+      assert.ok(rotated.newVoucherSigner.equals(newSigner));
+
+      const root = Array.from(Buffer.alloc(32, 7));
+      const rootSet = await eventNamed(
+        await program.methods
+          .setMerkleRoot(root)
+          .accounts({ saleConfig: sale, owner: owner.publicKey })
+          .signers([owner])
+          .rpc(),
+        "merkleRootSet"
+      );
+      assert.deepEqual(Array.from(rootSet.merkleRoot), root);
+
+      const paused = await eventNamed(
+        await program.methods
+          .pauseSale(true)
+          .accounts({ saleConfig: sale, authority: guardian.publicKey })
+          .signers([guardian])
+          .rpc(),
+        "salePaused"
+      );
+      assert.ok(paused.authority.equals(guardian.publicKey));
+      assert.isTrue(paused.claimsPaused);
+
+      const resumed = await eventNamed(
+        await program.methods
+          .resumeSale()
+          .accounts({ saleConfig: sale, authority: owner.publicKey })
+          .signers([owner])
+          .rpc(),
+        "saleResumed"
+      );
+      assert.ok(resumed.authority.equals(owner.publicKey));
+
+      const newOwner = await fundedKeypair();
+      const proposed = await eventNamed(
+        await program.methods
+          .proposeOwner(newOwner.publicKey)
+          .accounts({ saleConfig: sale, owner: owner.publicKey })
+          .signers([owner])
+          .rpc(),
+        "ownershipProposed"
+      );
+      assert.ok(proposed.owner.equals(owner.publicKey));
+      assert.ok(proposed.pendingOwner.equals(newOwner.publicKey));
+
+      const transferred = await eventNamed(
+        await program.methods
+          .acceptOwner()
+          .accounts({ saleConfig: sale, newOwner: newOwner.publicKey })
+          .signers([newOwner])
+          .rpc(),
+        "ownershipTransferred"
+      );
+      assert.ok(transferred.oldOwner.equals(owner.publicKey));
+      assert.ok(transferred.newOwner.equals(newOwner.publicKey));
+    });
+  });
+
+  describe("merkle allowlist", () => {
+    const sha256 = (...parts: Buffer[]): Buffer =>
+      createHash("sha256").update(Buffer.concat(parts)).digest();
+
+    // Leaf: buyer (32) + max_allocation (8) + tier (1)
+    const leafFor = (
+      buyerKey: PublicKey,
+      maxAllocation: number,
+      tier: number
+    ): Buffer =>
+      sha256(
+        buyerKey.toBuffer(),
+        new anchor.BN(maxAllocation).toArrayLike(Buffer, "le", 8),
+        Buffer.from([tier])
+      );
+
+    // Sorted pair hashing, matching the program's proof verification
+    const hashPair = (a: Buffer, b: Buffer): Buffer =>
+      Buffer.compare(a, b) <= 0 ? sha256(a, b) : sha256(b, a);
+
+    // Levels from the leaves up to the root; an odd node moves up unchanged
+    const merkleLevels = (leaves: Buffer[]): Buffer[][] => {
+      const levels = [leaves];
+      while (levels[levels.length - 1].length > 1) {
+        const level = levels[levels.length - 1];
+        const next: Buffer[] = [];
+        for (let i = 0; i < level.length; i += 2) {
+          next.push(
+            i + 1 < level.length ? hashPair(level[i], level[i + 1]) : level[i]
+          );
+        }
+        levels.push(next);
+      }
+      return levels;
+    };
+
+    const proofFor = (levels: Buffer[][], index: number): number[][] => {
+      const proof: number[][] = [];
+      for (const level of levels.slice(0, -1)) {
+        const sibling = index ^ 1;
+        if (sibling < level.length) {
+          proof.push(Array.from(level[sibling]));
+        }
+        index = Math.floor(index / 2);
+      }
+      return proof;
+    };
+
+    let owner: Keypair;
+    let sale: PublicKey;
+    let allowlisted: Keypair[];
+    let levels: Buffer[][];
+    const maxAllocations = [2_000, 1_000, 3_000];
+
+    const buyWithProof = (
+      buyerKeypair: Keypair,
+      allocation: number,
+      maxAllocation: number,
+      tier: number,
+      proof: number[][]
+    ) =>
+      program.methods
+        .buyWithProof(
+          new anchor.BN(allocation),
+          new anchor.BN(maxAllocation),
+          tier,
+          proof
+        )
+        .accounts({
+          saleConfig: sale,
+          buyerEscrow: buyerEscrowFor(sale, buyerKeypair.publicKey),
+          saleVault: saleVaultFor(sale),
+          buyer: buyerKeypair.publicKey,
+          treasury: treasury.publicKey,
+          paymentEscrow: paymentEscrowFor(sale),
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([buyerKeypair])
+        .rpc();
+
+    const setMerkleRoot = (signer: Keypair, root: number[] | null) =>
+      program.methods
+        .setMerkleRoot(root)
+        .accounts({ saleConfig: sale, owner: signer.publicKey })
+        .signers([signer])
+        .rpc();
+
+    before(async () => {
+      owner = await fundedKeypair();
+      ({ sale } = await initSale(owner));
+
+      allowlisted = [];
+      for (let i = 0; i < maxAllocations.length; i++) {
+        allowlisted.push(await fundedKeypair());
+      }
+      levels = merkleLevels(
+        allowlisted.map((wallet, i) =>
+          leafFor(wallet.publicKey, maxAllocations[i], i)
+        )
+      );
+    });
+
+    it("Rejects proof purchases until a root is set", async () => {
+      try {
+        await buyWithProof(
+          allowlisted[0],
+          1_000,
+          2_000,
+          0,
+          proofFor(levels, 0)
+        );
+        assert.fail("Expected purchase without a merkle root to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("MerkleRootNotSet");
+      }
+    });
+
+    it("Rejects setting the root by anyone but the owner", async () => {
+      const stranger = await fundedKeypair();
+      const root = Array.from(levels[levels.length - 1][0]);
+
+      try {
+        await setMerkleRoot(stranger, root);
+        assert.fail("Expected set_merkle_root by a stranger to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("ConstraintHasOne");
+      }
+    });
+
+    it("Allowlisted buyers purchase up to their leaf allocation", async () => {
+      const root = Array.from(levels[levels.length - 1][0]);
+      await setMerkleRoot(owner, root);
+
+      // The odd third leaf has a single-node proof
+      const buyTx = await buyWithProof(
+        allowlisted[2],
+        3_000,
+        3_000,
+        2,
+        proofFor(levels, 2)
+      );
+      const purchased = await eventNamed(buyTx, "tokensPurchased");
+      assert.equal(purchased.tier, 2);
+      assert.isNull(purchased.nonce);
+      await buyWithProof(allowlisted[0], 1_500, 2_000, 0, proofFor(levels, 0));
+
+      const escrow = await program.account.buyerEscrow.fetch(
+        buyerEscrowFor(sale, allowlisted[0].publicKey)
+      );
+      assert.equal(escrow.allocation.toString(), "1500");
+      assert.equal(
+        escrow.paid.toString(),
+        paymentFor(new anchor.BN(1_500)).toString()
+      );
+      const saleConfig = await program.account.saleConfig.fetch(sale);
+      assert.equal(saleConfig.sold.toString(), "4500");
+
+      try {
+        await buyWithProof(
+          allowlisted[0],
+          1_000,
+          2_000,
+          0,
+          proofFor(levels, 0)
+        );
+        assert.fail("Expected purchase beyond the leaf allocation to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("ExceedsAllocation");
+      }
+    });
+
+    it("Rejects inflated allocations and borrowed proofs", async () => {
+      try {
+        await buyWithProof(
+          allowlisted[1],
+          2_000,
+          5_000,
+          1,
+          proofFor(levels, 1)
+        );
+        assert.fail("Expected an inflated max allocation to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidMerkleProof");
+      }
+
+      const outsider = await fundedKeypair();
+      try {
+        await buyWithProof(outsider, 1_000, 1_000, 1, proofFor(levels, 1));
+        assert.fail("Expected a proof for another wallet to fail");
+      } catch (error) {
+        expect(error.toString()).to.include("InvalidMerkleProof");
+      }
+    });
   });
 });
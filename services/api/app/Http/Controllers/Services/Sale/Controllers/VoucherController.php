@@ -20,6 +20,23 @@
  */
 class VoucherController extends Controller
 {
+    /** Domain prefix of the signed voucher message, must match VOUCHER_DOMAIN in the program */
+    private const VOUCHER_DOMAIN = 'anchor-presale:voucher';
+
+    /** Voucher message layout version, must match VOUCHER_VERSION in the program */
+    private const VOUCHER_VERSION = 1;
+
+    /** Cluster tags signed into vouchers, must match the program's Cluster enum */
+    private const CLUSTER_TAGS = [
+        'mainnet-beta' => 0,
+        'devnet' => 1,
+        'testnet' => 2,
+        'localnet' => 3,
+    ];
+
+    /** Presale program ID, overridden by PRESALE_PROGRAM_ID */
+    private const PRESALE_PROGRAM_ID = '7RMrnnQC1pckXgLWdqw6mqQT5QSmyUSKjcsHmTt5CTQV';
+
     /**
      * Issue a presale voucher for a whitelisted buyer
      *
@@ -93,7 +110,8 @@ public function issueWhitelistVoucher(Request $request): JsonResponse
             ];
 
             // Create message to sign
-            // Format: buyer (32 bytes) + sale (32 bytes) + max_allocation (8 bytes LE) + nonce (8 bytes LE) + expiry_ts (8 bytes LE)
+            // Format: domain + program id (32 bytes) + version (1 byte) + cluster (1 byte) + buyer (32 bytes) + sale (32 bytes)
+            // + max_allocation (8 bytes LE) + nonce (8 bytes LE) + expiry_ts (8 bytes LE)
             $message = $this->serializeVoucherMessage($voucherData);
 
             // Sign the message using libsodium ed25519
@@ -215,8 +233,13 @@ private function loadVoucherSignerKeypair(): array
     /**
      * Serialize voucher data into the message format expected by the Solana program
      *
-     * Format: buyer (32) + sale (32) + max_allocation (8 LE) + nonce (8 LE) + expiry_ts (8 LE)
-     * Total: 88 bytes
+     * Mirrors VoucherData::message in the presale program:
+     * "anchor-presale:voucher" (22) + program id (32) + version (1) + cluster (1)
+     * + buyer (32) + sale (32) + max_allocation (8 LE) + nonce (8 LE) + expiry_ts (8 LE)
+     * Total: 144 bytes
+     *
+     * The cluster comes from myxn.network and must match the cluster the sale
+     * was initialized with, so a voucher is only valid on one cluster
      *
      * @param array $voucherData
      * @return string Binary message
@@ -225,23 +248,31 @@ private function loadVoucherSignerKeypair(): array
     private function serializeVoucherMessage(array $voucherData): string
     {
         // Decode base58 public keys to binary (32 bytes each)
+        $programIdBytes = $this->base58Decode(env('PRESALE_PROGRAM_ID', self::PRESALE_PROGRAM_ID));
         $buyerBytes = $this->base58Decode($voucherData['buyer']);
         $saleBytes = $this->base58Decode($voucherData['sale']);
 
-        if (strlen($buyerBytes) !== 32 || strlen($saleBytes) !== 32) {
+        if (strlen($programIdBytes) !== 32 || strlen($buyerBytes) !== 32 || strlen($saleBytes) !== 32) {
             throw new \Exception("Invalid public key length after decoding");
         }
 
+        $network = config('myxn.network');
+        if (!array_key_exists($network, self::CLUSTER_TAGS)) {
+            throw new \Exception("Unknown Solana network for vouchers: {$network}");
+        }
+
         // Pack integers as little-endian 64-bit unsigned
         $maxAllocationBytes = pack('P', $voucherData['max_allocation']); // P = unsigned 64-bit LE
         $nonceBytes = pack('P', $voucherData['nonce']);
         $expiryTsBytes = pack('q', $voucherData['expiry_ts']); // q = signed 64-bit LE
 
         // Concatenate all parts
-        $message = $buyerBytes . $saleBytes . $maxAllocationBytes . $nonceBytes . $expiryTsBytes;
+        $message = self::VOUCHER_DOMAIN . $programIdBytes . chr(self::VOUCHER_VERSION)
+            . chr(self::CLUSTER_TAGS[$network])
+            . $buyerBytes . $saleBytes . $maxAllocationBytes . $nonceBytes . $expiryTsBytes;
 
-        if (strlen($message) !== 88) {
-            throw new \Exception("Invalid message length: " . strlen($message) . " (expected 88 bytes)");
+        if (strlen($message) !== 144) {
+            throw new \Exception("Invalid message length: " . strlen($message) . " (expected 144 bytes)");
         }
 
         return $message;